@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+
+use arrow2::{
+    datatypes::{DataType, PhysicalType, PrimitiveType},
+    array::{Array, PrimitiveArray},
+    types::NativeType,
+    error::Error as ArrowError,
+};
+
+use crate::core::value::Value;
+use crate::error::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggFunc {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Count,
+    CountDistinct,
+    First,
+    Last,
+}
+
+impl AggFunc {
+    fn name(&self) -> &str {
+        match self {
+            AggFunc::Sum => "sum",
+            AggFunc::Mean => "mean",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+            AggFunc::Count => "count",
+            AggFunc::CountDistinct => "count_distinct",
+            AggFunc::First => "first",
+            AggFunc::Last => "last",
+        }
+    }
+}
+
+// Aggregation expression; column None is only valid for Count and counts all rows (count(*))
+#[derive(Clone, Debug)]
+pub struct Agg {
+    pub column: Option<String>,
+    pub func: AggFunc,
+    pub alias: Option<String>,
+}
+
+impl Agg {
+    pub fn new(column: &str, func: AggFunc) -> Self {
+        Self { column: Some(column.to_string()), func, alias: None }
+    }
+
+    pub fn sum(column: &str) -> Self {Self::new(column, AggFunc::Sum)}
+    pub fn mean(column: &str) -> Self {Self::new(column, AggFunc::Mean)}
+    pub fn min(column: &str) -> Self {Self::new(column, AggFunc::Min)}
+    pub fn max(column: &str) -> Self {Self::new(column, AggFunc::Max)}
+    pub fn count(column: &str) -> Self {Self::new(column, AggFunc::Count)}
+    pub fn count_distinct(column: &str) -> Self {Self::new(column, AggFunc::CountDistinct)}
+    pub fn first(column: &str) -> Self {Self::new(column, AggFunc::First)}
+    pub fn last(column: &str) -> Self {Self::new(column, AggFunc::Last)}
+    pub fn count_rows() -> Self {
+        Self { column: None, func: AggFunc::Count, alias: None }
+    }
+
+    pub fn alias(mut self, name: &str) -> Self {
+        self.alias = Some(name.to_string());
+        self
+    }
+
+    // Output column name, e.g. price_sum
+    pub fn name(&self) -> String {
+        match (&self.alias, &self.column) {
+            (Some(alias), _) => alias.clone(),
+            (None, Some(column)) => format!("{}_{}", column, self.func.name()),
+            (None, None) => self.func.name().to_string(),
+        }
+    }
+
+    // Output type given the input type
+    pub fn data_type(&self, input: &DataType) -> Result<DataType> {
+        match self.func {
+            AggFunc::Count | AggFunc::CountDistinct => Ok(DataType::Int64),
+            AggFunc::Min | AggFunc::Max | AggFunc::First | AggFunc::Last => Ok(input.clone()),
+            AggFunc::Mean => match input {
+                DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 | DataType::UInt8 | DataType::UInt16
+                    | DataType::UInt32 | DataType::UInt64 | DataType::Float32 | DataType::Float64 | DataType::Decimal(_, _) => Ok(DataType::Float64),
+                dt => Err(Error::UnsupportedType(format!("Mean is not implemented for {:?}", dt)))
+            },
+            AggFunc::Sum => match input {
+                DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => Ok(DataType::Int64),
+                DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => Ok(DataType::UInt64),
+                DataType::Float32 | DataType::Float64 => Ok(DataType::Float64),
+                DataType::Decimal(_, scale) => Ok(DataType::Decimal(38, *scale)),
+                dt => Err(Error::UnsupportedType(format!("Sum is not implemented for {:?}", dt)))
+            },
+        }
+    }
+}
+
+// Aggregate the values of one group. Nulls are ignored, except by First/Last which return the
+// value of the first/last row. Aggregates over only nulls are null, counts are 0.
+pub fn aggregate_values<I: Iterator<Item = Value>>(func: AggFunc, input: &DataType, values: I) -> Result<Value> {
+    let value = match func {
+        AggFunc::First => values.into_iter().next().unwrap_or(Value::Null),
+        AggFunc::Last => values.into_iter().last().unwrap_or(Value::Null),
+        AggFunc::Count => Value::Int(values.filter(|v| !v.is_null()).count() as i64),
+        AggFunc::CountDistinct => Value::Int(values.filter(|v| !v.is_null()).collect::<HashSet<Value>>().len() as i64),
+        AggFunc::Min => values.filter(|v| !v.is_null()).min().unwrap_or(Value::Null),
+        AggFunc::Max => values.filter(|v| !v.is_null()).max().unwrap_or(Value::Null),
+        AggFunc::Sum => {
+            let mut sum = None;
+            for v in values.filter(|v| !v.is_null()) {
+                sum = Some(match (sum, v) {
+                    (None, v) => v,
+                    (Some(Value::Int(a)), Value::Int(b)) => Value::Int(a.checked_add(b).ok_or(ArrowError::Overflow)?),
+                    (Some(Value::UInt(a)), Value::UInt(b)) => Value::UInt(a.checked_add(b).ok_or(ArrowError::Overflow)?),
+                    (Some(Value::Float(a)), Value::Float(b)) => Value::Float(a + b),
+                    (Some(Value::Decimal(a)), Value::Decimal(b)) => Value::Decimal(a.checked_add(b).ok_or(ArrowError::Overflow)?),
+                    _ => Value::Null,
+                });
+            }
+            sum.unwrap_or(Value::Null)
+        },
+        AggFunc::Mean => {
+            let scale = match input {
+                DataType::Decimal(_, scale) => 10f64.powi(*scale as i32),
+                _ => 1.0,
+            };
+            let (sum, count) = values.fold((0.0, 0), |(sum, count), v| match v {
+                Value::Int(v) => (sum + v as f64, count + 1),
+                Value::UInt(v) => (sum + v as f64, count + 1),
+                Value::Float(v) => (sum + v, count + 1),
+                Value::Decimal(v) => (sum + v as f64 / scale, count + 1),
+                _ => (sum, count),
+            });
+            if count > 0 {Value::Float(sum / count as f64)} else {Value::Null}
+        },
+    };
+    Ok(value)
+}
+
+// Native types aggregated directly on their arrays; Sum is the accumulator type of AggFunc::Sum
+trait Numeric: NativeType + PartialOrd + Into<Value> {
+    type Sum: Copy + Into<Value>;
+    fn add(sum: Option<Self::Sum>, v: Self) -> Option<Self::Sum>;
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! numeric {
+    ($t:ty, $sum:ty, $add:ident) => {
+        impl Numeric for $t {
+            type Sum = $sum;
+            fn add(sum: Option<$sum>, v: $t) -> Option<$sum> {
+                match sum {
+                    Some(sum) => $add(sum, v as $sum),
+                    None => Some(v as $sum),
+                }
+            }
+            fn to_f64(self) -> f64 {self as f64}
+        }
+    };
+}
+
+fn checked_i64(a: i64, b: i64) -> Option<i64> {a.checked_add(b)}
+fn checked_u64(a: u64, b: u64) -> Option<u64> {a.checked_add(b)}
+fn checked_i128(a: i128, b: i128) -> Option<i128> {a.checked_add(b)}
+fn float(a: f64, b: f64) -> Option<f64> {Some(a + b)}
+
+numeric!(i8, i64, checked_i64);
+numeric!(i16, i64, checked_i64);
+numeric!(i32, i64, checked_i64);
+numeric!(i64, i64, checked_i64);
+numeric!(u8, u64, checked_u64);
+numeric!(u16, u64, checked_u64);
+numeric!(u32, u64, checked_u64);
+numeric!(u64, u64, checked_u64);
+numeric!(i128, i128, checked_i128);
+numeric!(f32, f64, float);
+numeric!(f64, f64, float);
+
+// Sum, Mean, Min & Max over the valid rows of primitive arrays; scale divides decimals for Mean
+fn aggregate_numeric<'a, T: Numeric, I: Iterator<Item = (&'a dyn Array, usize)>>(func: AggFunc, rows: I, scale: f64) -> Result<Value> {
+    let mut values = Vec::new();
+    for (array, i) in rows {
+        let array = array
+            .as_any()
+            .downcast_ref::<PrimitiveArray<T>>()
+            .ok_or_else(|| Error::UnsupportedType(format!("{:?} is not a primitive array", array.data_type())))?;
+        if array.is_valid(i) {
+            values.push(array.value(i));
+        }
+    }
+    let value = match func {
+        AggFunc::Sum => {
+            let mut sum = None;
+            for v in values {
+                sum = Some(T::add(sum, v).ok_or(ArrowError::Overflow)?);
+            }
+            sum.map(|sum| sum.into())
+        },
+        AggFunc::Mean => match values.len() {
+            0 => None,
+            n => Some(Value::Float(values.iter().map(|v| v.to_f64() / scale).sum::<f64>() / n as f64)),
+        },
+        AggFunc::Min => values.into_iter().reduce(|a, b| if b < a {b} else {a}).map(|v| v.into()),
+        AggFunc::Max => values.into_iter().reduce(|a, b| if b > a {b} else {a}).map(|v| v.into()),
+        _ => return Err(Error::InvalidArgument(format!("{:?} is not a numeric aggregation", func))),
+    };
+    Ok(value.unwrap_or(Value::Null))
+}
+
+// Aggregate the rows of a group, given as row idxs into arrays (one array per chunk)
+pub fn aggregate_rows(agg: &Agg, arrays: &[&dyn Array], rows: &[(usize, Vec<u32>)]) -> Result<Value> {
+    let num_rows = rows.iter().map(|(_, idxs)| idxs.len()).sum::<usize>();
+    if agg.column.is_none() {
+        return Ok(Value::Int(num_rows as i64))
+    }
+    let input = arrays.first().map(|a| a.data_type().clone()).unwrap_or(DataType::Null);
+    let mut iter = rows
+        .iter()
+        .flat_map(|(chunk, idxs)| idxs.iter().map(move |i| (arrays[*chunk], *i as usize)));
+    let numeric = matches!(agg.func, AggFunc::Sum | AggFunc::Mean | AggFunc::Min | AggFunc::Max);
+    let physical = match input.to_physical_type() {
+        PhysicalType::Primitive(primitive) => Some(primitive),
+        _ => None,
+    };
+    match (agg.func, physical) {
+        (AggFunc::Count, _) => Ok(Value::Int(iter.filter(|(array, i)| array.is_valid(*i)).count() as i64)),
+        (AggFunc::First, _) => iter.next().map_or(Ok(Value::Null), |(array, i)| Value::from_array(array, i)),
+        (AggFunc::Last, _) => iter.last().map_or(Ok(Value::Null), |(array, i)| Value::from_array(array, i)),
+        (_, Some(PrimitiveType::Int8)) if numeric => aggregate_numeric::<i8, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Int16)) if numeric => aggregate_numeric::<i16, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Int32)) if numeric => aggregate_numeric::<i32, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Int64)) if numeric => aggregate_numeric::<i64, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::UInt8)) if numeric => aggregate_numeric::<u8, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::UInt16)) if numeric => aggregate_numeric::<u16, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::UInt32)) if numeric => aggregate_numeric::<u32, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::UInt64)) if numeric => aggregate_numeric::<u64, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Float32)) if numeric => aggregate_numeric::<f32, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Float64)) if numeric => aggregate_numeric::<f64, _>(agg.func, iter, 1.0),
+        (_, Some(PrimitiveType::Int128)) if numeric => {
+            let scale = match input {
+                DataType::Decimal(_, scale) => 10f64.powi(scale as i32),
+                _ => 1.0,
+            };
+            aggregate_numeric::<i128, _>(agg.func, iter, scale)
+        },
+        // strings, booleans, binaries & distinct counts compare on values
+        _ => {
+            let values = iter.map(|(array, i)| Value::from_array(array, i)).collect::<Result<Vec<Value>>>()?;
+            aggregate_values(agg.func, &input, values.into_iter())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_semantics() {
+        let values = vec![Value::Null, Value::Int(3), Value::Int(1), Value::Null, Value::Int(3)];
+        let agg = |func| aggregate_values(func, &DataType::Int32, values.clone().into_iter()).unwrap();
+        assert_eq!(agg(AggFunc::Sum), Value::Int(7));
+        assert_eq!(agg(AggFunc::Mean), Value::Float(7.0 / 3.0));
+        assert_eq!(agg(AggFunc::Min), Value::Int(1));
+        assert_eq!(agg(AggFunc::Max), Value::Int(3));
+        assert_eq!(agg(AggFunc::Count), Value::Int(3));
+        assert_eq!(agg(AggFunc::CountDistinct), Value::Int(2));
+        assert_eq!(agg(AggFunc::First), Value::Null);
+        assert_eq!(agg(AggFunc::Last), Value::Int(3));
+
+        let nulls = vec![Value::Null, Value::Null];
+        assert_eq!(aggregate_values(AggFunc::Sum, &DataType::Int32, nulls.clone().into_iter()).unwrap(), Value::Null);
+        assert_eq!(aggregate_values(AggFunc::Count, &DataType::Int32, nulls.into_iter()).unwrap(), Value::Int(0));
+    }
+
+    #[test]
+    fn test_typed_rows() {
+        let a1 = PrimitiveArray::<i64>::from(vec![Some(i64::MAX), None, Some(-2)]);
+        let a2 = PrimitiveArray::<i64>::from(vec![Some(1), Some(4)]);
+        let arrays = [&a1 as &dyn Array, &a2 as &dyn Array];
+        let agg = |agg: Agg, rows: &[(usize, Vec<u32>)]| aggregate_rows(&agg, &arrays, rows);
+        let rows = vec![(0, vec![1, 2]), (1, vec![0, 1])];
+        assert_eq!(agg(Agg::sum("c"), &rows).unwrap(), Value::Int(3));
+        assert_eq!(agg(Agg::mean("c"), &rows).unwrap(), Value::Float(1.0));
+        assert_eq!(agg(Agg::min("c"), &rows).unwrap(), Value::Int(-2));
+        assert_eq!(agg(Agg::max("c"), &rows).unwrap(), Value::Int(4));
+        assert_eq!(agg(Agg::count("c"), &rows).unwrap(), Value::Int(3));
+        assert_eq!(agg(Agg::first("c"), &rows).unwrap(), Value::Null);
+        assert!(agg(Agg::sum("c"), &[(0, vec![0]), (1, vec![0])]).is_err());
+    }
+}
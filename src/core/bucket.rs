@@ -0,0 +1,99 @@
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use arrow2::{array::Array, chunk::Chunk};
+
+use crate::core::chunks::chunk_take;
+use crate::core::table::Table;
+use crate::core::value::{Value, FNV_OFFSET, stable_hash, stable_hash_array};
+use crate::error::{Error, Result};
+
+// Hash bucketing (within partitions): rows go to one of num_buckets buckets on a stable hash of their
+// bucket column values, so a key always lands in the same bucket, whichever process wrote it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BucketSpec {
+    pub columns: Vec<String>,
+    pub num_buckets: usize,
+}
+
+impl BucketSpec {
+    pub fn new(columns: Vec<String>, num_buckets: usize) -> Self {
+        Self { columns, num_buckets }
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.columns.is_empty() || self.num_buckets == 0 {
+            return Err(Error::InvalidArgument(format!("Bucketing needs columns and at least one bucket, got {:?}", self)))
+        }
+        Ok(())
+    }
+
+    // Bucket of a key (the values of the bucket columns, in order)
+    pub fn bucket(&self, key: &[Value]) -> usize {
+        (stable_hash(key) % self.num_buckets as u64) as usize
+    }
+
+    // Bucket of every row of table, per chunk
+    pub fn buckets(&self, table: &Table) -> Result<Vec<Vec<usize>>> {
+        self.check()?;
+        let idxs = self.columns
+            .iter()
+            .map(|column| table.position(column))
+            .collect::<Result<Vec<usize>>>()?;
+        table.chunks
+            .par_iter()
+            .map(|chunk| {
+                let mut hashes = vec![FNV_OFFSET; chunk.len()];
+                for idx in &idxs {
+                    stable_hash_array(chunk.columns()[*idx].as_ref(), &mut hashes)?;
+                }
+                Ok(hashes.into_iter().map(|hash| (hash % self.num_buckets as u64) as usize).collect())
+            })
+            .collect()
+    }
+
+    // Rows of table per (non empty) bucket, ordered by bucket
+    pub fn split(&self, table: &Table) -> Result<Vec<(usize, Table)>> {
+        let buckets = self.buckets(table)?;
+        let mut chunks: Vec<Vec<Chunk<Box<dyn Array>>>> = vec![Vec::new(); self.num_buckets];
+        for (chunk, buckets) in table.chunks.iter().zip(buckets) {
+            let mut idxs: Vec<Vec<u32>> = vec![Vec::new(); self.num_buckets];
+            for (i, bucket) in buckets.into_iter().enumerate() {
+                idxs[bucket].push(i as u32);
+            }
+            for (bucket, idxs) in idxs.iter().enumerate().filter(|(_, idxs)| !idxs.is_empty()) {
+                chunks[bucket].push(chunk_take(chunk, idxs)?);
+            }
+        }
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .filter(|(_, chunks)| !chunks.is_empty())
+            .map(|(bucket, chunks)| (bucket, Table::new(table.fields.clone(), chunks)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::factory::create_random_table;
+
+    #[test]
+    fn test_split() {
+        let t = create_random_table(1);
+        let spec = BucketSpec::new(vec!["c2".to_string()], 8);
+        let split = spec.split(&t).unwrap();
+        assert_eq!(split.len(), 8);
+        assert_eq!(split.iter().map(|(_, t)| t.num_rows()).sum::<usize>(), t.num_rows());
+        // every row is in the bucket of its key
+        for (bucket, table) in split.iter().take(2) {
+            assert!(spec.buckets(table).unwrap().iter().flatten().all(|b| b == bucket));
+        }
+        // c1 only has 10 keys
+        let spec = BucketSpec::new(vec!["c1".to_string()], 64);
+        assert!(spec.split(&t).unwrap().len() <= 10);
+        assert!(matches!(BucketSpec::new(vec!["c9".to_string()], 8).split(&t), Err(Error::ColumnNotFound(_))));
+        assert!(matches!(BucketSpec::new(vec!["c1".to_string()], 0).split(&t), Err(Error::InvalidArgument(_))));
+    }
+}
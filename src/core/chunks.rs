@@ -6,14 +6,16 @@ use arrow2::{
     compute::take::take,
 };
 
-pub fn chunk_take(chunk: &Chunk<Box<dyn Array>>, idxs: &Vec<u32>) -> Chunk<Box<dyn Array>> {
+use crate::error::Result;
+
+pub fn chunk_take(chunk: &Chunk<Box<dyn Array>>, idxs: &[u32]) -> Result<Chunk<Box<dyn Array>>> {
     let idxs = PrimitiveArray::from(idxs.iter().map(|x| Some(*x)).collect::<Vec<Option<u32>>>());
     let arrays_new = chunk
         .columns()
         .iter()
-        .map(|array| take(array.as_ref(), &idxs).unwrap())
-        .collect::<Vec<Box<dyn Array>>>();
-    Chunk::new(arrays_new)
+        .map(|array| take(array.as_ref(), &idxs))
+        .collect::<arrow2::error::Result<Vec<Box<dyn Array>>>>()?;
+    Ok(Chunk::new(arrays_new))
 }
 
 // pub fn chunks_take(chunk: Vec<&Chunk<Box<dyn Array>>>, idxs: &Vec<u32>) -> Chunk<Box<dyn Array>> {
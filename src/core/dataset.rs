@@ -1,30 +1,87 @@
 use std::path::Path;
 use std::fs;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde_json;
 use serde::{Serialize, Deserialize};
 
 use rayon::prelude::*;
 
+use arrow2::{
+    array::Array,
+    chunk::Chunk,
+    compute::cast::{cast, CastOptions},
+    datatypes::{DataType, Field},
+};
+
 use crate::core::table::Table;
-use crate::io::parquet::read::read_parquet;
+use crate::core::value::Value;
+use crate::core::log::{Log, Commit, LOG_DIR};
+use crate::core::bucket::BucketSpec;
+use crate::core::join::JoinType;
+use crate::core::filter::FilterExpr;
+use crate::io::ReadOptions;
+use crate::io::parquet::read::read_parquet_with;
+use crate::io::ipc::read::read_ipc_with;
+use crate::io::csv::CsvOptions;
+use crate::io::csv::read::read_csv_with;
+use crate::io::json::JsonOptions;
+use crate::io::json::read::read_ndjson_with;
+use crate::io::parquet::write::WriteOptions;
+use crate::error::{Error, Result};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Format {
     Parquet,
     Ipc,
     Csv,
+    Json, // Newline-delimited
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Format {
+    pub fn extension(&self) -> &str {
+        match self {
+            Format::Parquet => "parquet",
+            Format::Ipc => "arrow",
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+
+    // Format of a data file, from its extension
+    pub fn from_path(path: &str) -> Result<Self> {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("parquet") => Ok(Format::Parquet),
+            Some("arrow") => Ok(Format::Ipc),
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            _ => Err(Error::UnsupportedType(format!("Format of {} is not known", path))),
+        }
+    }
+}
+
+// Read a data file in the format given by its extension. CSV & JSON files are typed by schema when known.
+fn read_file(path: &str, schema: &Option<Vec<Field>>, options: &ReadOptions) -> Result<Table> {
+    match Format::from_path(path)? {
+        Format::Parquet => read_parquet_with(path, options),
+        Format::Ipc => read_ipc_with(path, options),
+        Format::Csv => read_csv_with(path, &CsvOptions { schema: schema.clone(), ..Default::default() }, options),
+        Format::Json => read_ndjson_with(path, &JsonOptions { schema: schema.clone(), ..Default::default() }, options),
+    }
+}
+
+// Codec, with an optional level for Gzip (0-10), Brotli (0-11) and Zstd (1-22)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Compression {
+    Uncompressed,
     Snappy,
-    // Gzip,
+    Gzip(Option<u8>),
     // Lzo,
-    // Brotli,
+    Brotli(Option<u32>),
     // Lz4,
-    // Zstd,
+    Zstd(Option<i32>),
     Lz4Raw,
 }
 
@@ -33,45 +90,163 @@ pub struct DatasetStorage {
     root: String, // Root folder (relative to code)
     format: Format,
     compression: Option<Compression>,
-    // versioned: Bool,
-    // acid: Option<Bool>
+    #[serde(default)]
+    write_options: WriteOptions, // Compression overrides the codec of these
+    #[serde(default)]
+    versioned: bool, // Keep files removed from the log (for time travel), otherwise they are deleted after the commit
 }
 
 impl DatasetStorage {
     pub fn new( root: String, format: Format, compression: Option<Compression>) -> Self {
-        Self { root, format, compression }
+        Self { root, format, compression, write_options: WriteOptions::default(), versioned: false }
+    }
+
+    pub fn with_write_options(mut self, write_options: WriteOptions) -> Self {
+        self.write_options = write_options;
+        self
+    }
+
+    // Options parts are written with
+    pub fn write_options(&self) -> WriteOptions {
+        let mut options = self.write_options.clone();
+        if let Some(compression) = self.compression {
+            options.compression = compression;
+        }
+        options
+    }
+
+    pub fn with_versioned(mut self, versioned: bool) -> Self {
+        self.versioned = versioned;
+        self
+    }
+}
+
+// Options for compaction: the size (in bytes, on disk) files are combined up to, columns to sort the rows of
+// each partition on (ascending) and a new bucket spec
+#[derive(Clone, Debug)]
+pub struct CompactOptions {
+    pub target_file_size: usize,
+    pub sort_by: Option<Vec<String>>,
+    pub buckets: Option<BucketSpec>,
+}
+
+impl CompactOptions {
+    pub fn new(target_file_size: usize) -> Self {
+        Self { target_file_size, sort_by: None, buckets: None }
+    }
+
+    pub fn with_sort_by(mut self, columns: Vec<String>) -> Self {
+        self.sort_by = Some(columns);
+        self
+    }
+
+    pub fn with_buckets(mut self, buckets: BucketSpec) -> Self {
+        self.buckets = Some(buckets);
+        self
     }
 }
 
 pub struct DatasetPart {
     table: Option<Table>, // For lazy loading
-    filters: Option<HashMap<String, String>>,
+    filters: Option<HashMap<String, Value>>,
     path: Option<String>,
+    schema: Option<Vec<Field>>, // Schema of the file, for formats that don't store one (csv, json)
+    bucket: Option<usize>, // Hash bucket of the rows (bucketed datasets)
+    partial: bool, // The table holds only the rows / columns of the file selected when loading
 }
 
 impl DatasetPart {
-    pub fn new( table: Option<Table>, filters: Option<HashMap<String, String>>, path: Option<String>) -> Self {
-        Self { table, filters, path }
+    pub fn new( table: Option<Table>, filters: Option<HashMap<String, Value>>, path: Option<String>) -> Self {
+        Self { table, filters, path, schema: None, bucket: None, partial: false }
+    }
+
+    pub fn with_bucket(mut self, bucket: Option<usize>) -> Self {
+        self.bucket = bucket;
+        self
+    }
+
+    pub fn bucket(&self) -> Option<usize> {
+        self.bucket
     }
 
-    pub fn partition_path(&self, partitions: &Option<Vec<String>>) -> String {
+    pub fn with_schema(mut self, schema: Option<Vec<Field>>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn partition_path(&self, partitions: &Option<Vec<String>>) -> Result<String> {
         match &partitions {
             Some(partitions) => {
                 let mut parts = Vec::new();
                 for p in partitions {
-                    let v = self.filters.as_ref().unwrap().get(p).unwrap();
-                    parts.push(format!("{}={}", p, v));
+                    let v = self.filters
+                        .as_ref()
+                        .and_then(|f| f.get(p))
+                        .ok_or_else(|| Error::ColumnNotFound(p.clone()))?;
+                    parts.push(format!("{}={}", p, v.to_path()));
                 }
-                parts.join("/")
+                Ok(parts.join("/"))
             },
-            None => "".to_string()
+            None => Ok("".to_string())
+        }
+    }
+
+    // Typed partition values of this part, in the order of partitions
+    pub fn partition_values(&self, partitions: &[String]) -> Vec<Value> {
+        partitions
+            .iter()
+            .map(|p| self.filters.as_ref().and_then(|f| f.get(p)).cloned().unwrap_or(Value::Null))
+            .collect()
+    }
+
+    // Whether rows of this part can match predicate, judged by its partition values (typed by fields)
+    pub fn matches(&self, fields: &[Field], predicate: &FilterExpr) -> Result<bool> {
+        let empty = HashMap::new();
+        let values = self.filters.as_ref().unwrap_or(&empty);
+        Ok(predicate.eval_values(fields, values)? != Some(false))
+    }
+
+    // Table of this part, read from its path when not loaded
+    pub fn read(&self) -> Result<Table> {
+        match (&self.table, &self.path) {
+            (Some(table), _) => Ok(table.clone()),
+            (None, Some(path)) => read_file(path, &self.schema, &ReadOptions::default()),
+            (None, None) => Err(Error::InvalidArgument("Path was not specified".to_string()))
         }
     }
 
-    pub fn load(&mut self) {
+    // Rows of this part matching predicate; parquet row groups are skipped on their statistics when read from its path
+    pub fn read_where(&self, predicate: &FilterExpr) -> Result<Table> {
+        match (&self.table, &self.path) {
+            (Some(table), _) => table.filter(predicate),
+            (None, Some(path)) => read_file(path, &self.schema, &ReadOptions { predicate: Some(predicate.clone()), ..Default::default() }),
+            (None, None) => Err(Error::InvalidArgument("Path was not specified".to_string()))
+        }
+    }
+
+    // Whole table of this part: read again from its path when only a selection of it was loaded. Writes that
+    // replace the file of a part use it, so rows & columns left out when loading are not lost.
+    pub fn read_all(&self) -> Result<Table> {
+        match (&self.table, &self.path) {
+            (Some(table), _) if !self.partial => Ok(table.clone()),
+            (_, Some(path)) => read_file(path, &self.schema, &ReadOptions::default()),
+            (_, None) => Err(Error::InvalidArgument("Path was not specified".to_string()))
+        }
+    }
+
+    pub fn load(&mut self) -> Result<()> {
+        self.load_with(&ReadOptions::default())
+    }
+
+    // Load only the columns, rows and row groups selected by options
+    pub fn load_with(&mut self, options: &ReadOptions) -> Result<()> {
         match &self.path {
-            Some(path) => {self.table = Some(read_parquet(path).unwrap());},
-            None => println!("Path was not specified!")
+            Some(path) => {
+                self.table = Some(read_file(path, &self.schema, options)?);
+                self.partial = options.is_partial();
+                Ok(())
+            },
+            None => Err(Error::InvalidArgument("Path was not specified".to_string()))
         }
     }
 }
@@ -79,55 +254,173 @@ impl DatasetPart {
 #[derive(Serialize, Deserialize)]
 pub struct Dataset {
     pub partitions: Option<Vec<String>>, // File based partitioning columns
-    pub buckets: Option<Vec<String>>, // Hash bucketing columns (within partitions)
+    pub buckets: Option<BucketSpec>, // Hash bucketing columns & number of buckets (within partitions)
     #[serde(skip_serializing, skip_deserializing)]
     pub parts: Vec<DatasetPart>, // Underlying parts (referencing to tables)
-    pub storage: Option<DatasetStorage> // Storage options
+    pub storage: Option<DatasetStorage>, // Storage options
+    #[serde(default)]
+    pub schema: Option<Vec<Field>>, // Schema of the underlying tables
+    #[serde(skip_serializing, skip_deserializing)]
+    pub version: Option<u64>, // Version of the transaction log this dataset is based on
+    #[serde(skip_serializing, skip_deserializing)]
+    pruned: Vec<String>, // Committed files (relative to root) left out by the predicate it was opened with
+}
+
+// Counter to keep file names unique within a process (on top of the timestamp)
+static FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Timestamp (ns) followed by the counter, e.g. 00017b9a1e2c3d4e5f60-0003
+fn unique_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let count = FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:020x}-{:04}", nanos, count)
 }
 
-fn extract_files<'a>(dir: &Path, contains: &String, files: &'a mut Vec<String>) -> &'a mut Vec<String> {
+// part-<unique id>.<ext>, with the bucket appended for bucketed datasets: part-<unique id>_<bucket:05>.<ext>
+fn unique_file_name(format: &Format, bucket: Option<usize>) -> String {
+    match bucket {
+        Some(bucket) => format!("part-{}_{:05}.{}", unique_id(), bucket, format.extension()),
+        None => format!("part-{}.{}", unique_id(), format.extension()),
+    }
+}
+
+// Bucket of a data file, from its name. Only names as written by unique_file_name have a bucket, so any
+// other file (e.g. data_2023.parquet) is not mistaken for one.
+fn bucket_from_path(path: &str) -> Option<usize> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    let (id, bucket) = stem.strip_prefix("part-")?.rsplit_once('_')?;
+    let (nanos, count) = id.split_once('-')?;
+    let digits = |s: &str, radix: u32| !s.is_empty() && s.chars().all(|c| c.is_digit(radix));
+    if nanos.len() == 20 && digits(nanos, 16) && digits(count, 10) && bucket.len() >= 5 && digits(bucket, 10) {
+        bucket.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn extract_files<'a>(dir: &Path, contains: &str, files: &'a mut Vec<String>) -> Result<&'a mut Vec<String>> {
     if dir.is_dir() {
-        for entry in fs::read_dir(dir).unwrap() {
-            let path = entry.unwrap().path();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
             if path.is_dir() {
-                extract_files(&path, contains, files);
+                extract_files(&path, contains, files)?;
             } else {
-                let file = path.to_str().unwrap().to_string();
+                let file = path.to_string_lossy().to_string();
                 if file.contains(contains) {
                     files.push(file);
                 }
             }
         }
     }
-    files
+    Ok(files)
+}
+
+// Data files under root (relative to root), for datasets written before the transaction log.
+// The manifest and the log are json files too, they are never data files.
+fn legacy_files(root: &str, format: &Format) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    extract_files(Path::new(root), &format!(".{}", format.extension()), &mut files)?;
+    Ok(files
+        .iter()
+        .map(|f| relative_path(root, f))
+        .filter(|f| f != "manifest.json" && !Path::new(f).starts_with(LOG_DIR))
+        .collect())
+}
+
+// One part per bucket of the rows of a (loaded) part; the part itself without a bucket spec
+fn bucketize_on(spec: Option<&BucketSpec>, part: DatasetPart) -> Result<Vec<DatasetPart>> {
+    match (spec, &part.table) {
+        (Some(spec), Some(table)) => Ok(spec
+            .split(table)?
+            .into_iter()
+            .map(|(bucket, table)| DatasetPart::new(Some(table), part.filters.clone(), None).with_bucket(Some(bucket)))
+            .collect()),
+        _ => Ok(vec![part]),
+    }
+}
+
+// Distinct values of columns over the rows of table
+fn key_values(table: &Table, columns: &[String]) -> Result<HashSet<Vec<Value>>> {
+    let idxs = columns.iter().map(|c| table.position(c)).collect::<Result<Vec<usize>>>()?;
+    let mut values = HashSet::new();
+    for chunk in &table.chunks {
+        for i in 0..chunk.len() {
+            values.insert(idxs.iter().map(|idx| Value::from_array(chunk.columns()[*idx].as_ref(), i)).collect::<Result<Vec<Value>>>()?);
+        }
+    }
+    Ok(values)
 }
 
-fn find_parts(root: &String, contains: &String, lazy: bool) -> Vec<DatasetPart> {
-    let mut empty = Vec::new();
-    let root_files = extract_files(&Path::new(root), contains, &mut empty);
+// Rows of parts in a single table; an empty table with fields without parts
+fn read_parts(parts: &[&DatasetPart], fields: Vec<Field>) -> Result<Table> {
+    let mut tables = parts.iter().map(|p| p.read()).collect::<Result<Vec<Table>>>()?.into_iter().filter(|t| t.num_rows() > 0);
+    let mut result = match tables.next() {
+        Some(table) => table,
+        None => return Ok(Table::empty(fields)),
+    };
+    for mut table in tables {
+        result.append(&mut table)?;
+    }
+    Ok(result)
+}
 
-    let parts = root_files 
+fn relative_path(root: &str, path: &str) -> String {
+    match Path::new(path).strip_prefix(root) {
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+// Parts for data files (relative to root), with partition values parsed from the hive style path.
+// Parts whose partition values can't match the predicate of options are skipped before they are loaded.
+fn find_parts(root: &str, files: &[String], schema: &Option<Vec<Field>>, types: &HashMap<String, DataType>, buckets: &Option<BucketSpec>, options: &ReadOptions, lazy: bool) -> Result<Vec<DatasetPart>> {
+    let fields = types.iter().map(|(name, data_type)| Field::new(name, data_type.clone(), true)).collect::<Vec<Field>>();
+    let parts = files 
         .par_iter()
-        .map(|path| {
+        .map(|file| {
             let mut filters = HashMap::new();
-            for v in path.split("/").collect::<Vec<&str>>() {
+            for v in file.split("/").collect::<Vec<&str>>() {
                 if v.contains("=") {
                     let arr = v.split("=").collect::<Vec<&str>>();
-                    filters.insert(arr[0].to_string(), arr[1].to_string());
+                    let value = match types.get(arr[0]) {
+                        Some(data_type) => Value::from_path(arr[1], data_type)?,
+                        None => Value::Utf8(arr[1].to_string()),
+                    };
+                    filters.insert(arr[0].to_string(), value);
                 }
             }
-            let mut part = DatasetPart::new(None, Some(filters), Some(path.clone()));
-            if !lazy {part.load()};
-            part
+            let path = Path::new(root).join(file).to_string_lossy().to_string();
+            let mut part = DatasetPart::new(None, Some(filters), Some(path))
+                .with_schema(schema.clone())
+                .with_bucket(buckets.as_ref().and_then(|spec| bucket_from_path(file).filter(|b| *b < spec.num_buckets)));
+            if let Some(predicate) = &options.predicate {
+                if !part.matches(&fields, predicate)? {
+                    return Ok(None)
+                }
+            }
+            if !lazy {part.load_with(options)?};
+            Ok(Some(part))
         })
-        .collect::<Vec<DatasetPart>>();
-    parts
+        .collect::<Result<Vec<Option<DatasetPart>>>>()?;
+    Ok(parts.into_iter().flatten().collect())
 }
 
 impl Dataset {
     // CREATION
-    pub fn new(partitions: Option<Vec<String>>, buckets: Option<Vec<String>>, parts: Vec<DatasetPart>, storage: Option<DatasetStorage>) -> Self {
-        Self { partitions, buckets, parts, storage }
+    pub fn new(partitions: Option<Vec<String>>, buckets: Option<BucketSpec>, parts: Vec<DatasetPart>, storage: Option<DatasetStorage>, schema: Option<Vec<Field>>) -> Self {
+        Self { partitions, buckets, parts, storage, schema, version: None, pruned: vec![] }
+    }
+
+    // Types of the partition columns (as far as known from the schema)
+    fn partition_types(&self) -> HashMap<String, DataType> {
+        match (&self.partitions, &self.schema) {
+            (Some(partitions), Some(schema)) => schema
+                .iter()
+                .filter(|f| partitions.contains(&f.name))
+                .map(|f| (f.name.clone(), f.data_type.clone()))
+                .collect(),
+            _ => HashMap::new()
+        }
     }
 
     // Utils
@@ -135,57 +428,1104 @@ impl Dataset {
     // pub fn is_bucketized(&self) -> bool {self.buckets.is_some()}
 
     // TABLE INTERACTIONS
-    // pub fn append(&self, table: &Table) {}
-    // pub fn upsert(&self, table: &Table) {}
-    // pub fn delete(&self, table: &Table) {}
-    
+
+    fn check_schema(&self, table: &Table) -> Result<()> {
+        match &self.schema {
+            Some(schema) if schema != &table.fields => {
+                Err(Error::SchemaMismatch(format!("Table {:?} does not match dataset {:?}", table.columns(), schema.iter().map(|f| &f.name).collect::<Vec<&String>>())))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // Split a table into parts along the partition columns, then along the buckets within each partition
+    pub fn split(&self, table: &Table) -> Result<Vec<DatasetPart>> {
+        let parts = self.split_partitions(table)?;
+        if self.buckets.is_none() {
+            return Ok(parts)
+        }
+        let parts = parts
+            .into_par_iter()
+            .map(|p| self.bucketize(p))
+            .collect::<Result<Vec<Vec<DatasetPart>>>>()?;
+        Ok(parts.into_iter().flatten().collect())
+    }
+
+    fn split_partitions(&self, table: &Table) -> Result<Vec<DatasetPart>> {
+        match &self.partitions {
+            Some(partitions) => table.groupby(partitions)?.parts(),
+            None => Ok(vec![DatasetPart::new(Some(table.clone()), Some(HashMap::new()), None)]),
+        }
+    }
+
+    // One part per bucket of the rows of a (loaded) part; the part itself when the dataset is not bucketed
+    fn bucketize(&self, part: DatasetPart) -> Result<Vec<DatasetPart>> {
+        bucketize_on(self.buckets.as_ref(), part)
+    }
+
+    // Write the table of a part to a new file within its partition
+    fn write_part(&self, part: &mut DatasetPart) -> Result<()> {
+        let storage = self.storage.as_ref().ok_or_else(|| Error::Manifest("Storage options are not set on dataset".to_string()))?;
+        let ppath = part.partition_path(&self.partitions)?;
+        let fpath = Path::new(&storage.root).join(ppath).join(unique_file_name(&storage.format, part.bucket)).to_string_lossy().to_string();
+        if let Some(t) = &part.table {
+            match storage.format {
+                Format::Parquet => t.to_parquet_with(&fpath, &storage.write_options())?,
+                Format::Ipc => t.to_ipc_with(&fpath, &storage.compression)?,
+                Format::Csv => t.to_csv(&fpath)?,
+                Format::Json => t.to_ndjson(&fpath)?,
+            }
+            part.schema = Some(t.fields.clone());
+        }
+        part.path = Some(fpath);
+        Ok(())
+    }
+
+    // Write the rows of table as new files into their (hive) partitions; existing files are left as they are
+    pub fn append(&mut self, table: &Table) -> Result<()> {
+        let parts = self.write_table(table)?;
+        if parts.is_empty() {
+            return Ok(())
+        }
+        let add = self.part_files(&parts);
+        self.parts.extend(parts);
+        self.commit("append", add, vec![])
+    }
+
+    // Append a stream of tables (e.g. from read_parquet_stream) batch by batch, in a single commit.
+    // Parts are written per batch and not kept in memory.
+    pub fn append_stream<I>(&mut self, tables: I) -> Result<()>
+    where I: Iterator<Item = Result<Table>> {
+        let mut parts = Vec::new();
+        for table in tables {
+            for mut part in self.write_table(&table?)? {
+                part.table = None;
+                parts.push(part);
+            }
+        }
+        if parts.is_empty() {
+            return Ok(())
+        }
+        let add = self.part_files(&parts);
+        self.parts.extend(parts);
+        self.commit("append", add, vec![])
+    }
+
+    // Write the parts of table to new files (not committed yet)
+    fn write_table(&mut self, table: &Table) -> Result<Vec<DatasetPart>> {
+        self.check_schema(table)?;
+        if table.num_rows() == 0 {
+            return Ok(vec![])
+        }
+        let parts = self.split(table)?
+            .into_par_iter()
+            .map(|mut p| {
+                self.write_part(&mut p)?;
+                Ok(p)
+            })
+            .collect::<Result<Vec<DatasetPart>>>()?;
+
+        if self.schema.is_none() {
+            self.schema = Some(table.fields.clone());
+        }
+        Ok(parts)
+    }
+
+    // Rows matching predicate. Parts are pruned on their partition values first, so only the parts
+    // that can match are read; the predicate is applied to the rows of parts that may partially match.
+    pub fn scan(&self, predicate: &FilterExpr) -> Result<Table> {
+        let fields = self.schema.clone().unwrap_or_default();
+        let empty = HashMap::new();
+        let tables = self.parts
+            .par_iter()
+            .map(|part| {
+                match predicate.eval_values(&fields, part.filters.as_ref().unwrap_or(&empty))? {
+                    Some(false) => Ok(None),
+                    Some(true) => Ok(Some(part.read()?)),
+                    None => Ok(Some(part.read_where(predicate)?)),
+                }
+            })
+            .collect::<Result<Vec<Option<Table>>>>()?;
+
+        // Parts may have been loaded with a projection, so the first table sets the fields
+        let mut tables = tables.into_iter().flatten();
+        let mut result = tables.next().unwrap_or_else(|| Table::empty(fields));
+        for mut table in tables {
+            result.append(&mut table)?;
+        }
+        Ok(result)
+    }
+
+    // Upsert rows on key columns; only partitions present in table are read and rewritten.
+    // Keys are matched within a partition.
+    pub fn upsert(&mut self, table: &Table, columns: &[String]) -> Result<()> {
+        if self.schema.is_none() {
+            self.schema = Some(table.fields.clone());
+        }
+        self.rewrite("upsert", table, true, |existing, incoming| existing.upsert(incoming, columns))
+    }
+
+    // Delete rows matching the keys in table; only partitions present in table are read and rewritten
+    pub fn delete(&mut self, table: &Table, columns: &[String]) -> Result<()> {
+        self.rewrite("delete", table, false, |existing, incoming| existing.delete(incoming, columns))
+    }
+
+    // Rewrite the partitions of table with merge(existing, incoming). Partitions without existing parts are
+    // written as is when insert is set. The replaced files are removed from the log in the same commit.
+    // Bucketed datasets rewrite all buckets of the partition, as keys may not be the bucket columns.
+    fn rewrite<F>(&mut self, operation: &str, table: &Table, insert: bool, merge: F) -> Result<()>
+    where F: Fn(&Table, &Table) -> Result<Table> + Sync {
+        self.check_schema(table)?;
+        if table.num_rows() == 0 {
+            return Ok(())
+        }
+
+        // Existing parts per partition
+        let incoming = self.split_partitions(table)?;
+        self.restore_pruned(&incoming)?;
+        let mut existing: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, p) in self.parts.iter().enumerate() {
+            existing.entry(p.partition_path(&self.partitions)?).or_default().push(i);
+        }
+
+        let results = incoming
+            .into_par_iter()
+            .map(|mut incoming| {
+                let ppath = incoming.partition_path(&self.partitions)?;
+                let idxs = existing.get(&ppath).cloned().unwrap_or_default();
+                if idxs.is_empty() {
+                    if !insert {
+                        return Ok(None)
+                    }
+                    let mut parts = self.bucketize(incoming)?;
+                    for part in parts.iter_mut() {
+                        self.write_part(part)?;
+                    }
+                    return Ok(Some((idxs, parts)))
+                }
+
+                let mut current = self.parts[idxs[0]].read_all()?;
+                for i in &idxs[1..] {
+                    current.append(&mut self.parts[*i].read_all()?)?;
+                }
+                let merged = merge(&current, &incoming.read()?)?;
+                if merged.num_rows() == 0 {
+                    return Ok(Some((idxs, vec![])))
+                }
+                let mut parts = self.bucketize(DatasetPart::new(Some(merged), incoming.filters.take(), None))?;
+                for part in parts.iter_mut() {
+                    self.write_part(part)?;
+                }
+                Ok(Some((idxs, parts)))
+            })
+            .collect::<Result<Vec<Option<(Vec<usize>, Vec<DatasetPart>)>>>>()?;
+
+        self.swap(operation, results.into_iter().flatten().collect())
+    }
+
+    // Add the parts of the pruned files in the partitions of incoming back (unloaded), so that rewriting
+    // these partitions merges with all their rows
+    fn restore_pruned(&mut self, incoming: &[DatasetPart]) -> Result<()> {
+        if self.pruned.is_empty() {
+            return Ok(())
+        }
+        let ppaths = incoming.iter().map(|p| p.partition_path(&self.partitions)).collect::<Result<HashSet<String>>>()?;
+        let (restored, pruned): (Vec<String>, Vec<String>) = std::mem::take(&mut self.pruned)
+            .into_iter()
+            .partition(|f| ppaths.contains(&Path::new(f).parent().map(|d| d.to_string_lossy().to_string()).unwrap_or_default()));
+        self.pruned = pruned;
+        let root = self.storage.as_ref().map(|s| s.root.clone()).unwrap_or_default();
+        let parts = find_parts(&root, &restored, &self.schema, &self.partition_types(), &self.buckets, &ReadOptions::default(), true)?;
+        self.parts.extend(parts);
+        Ok(())
+    }
+
+    // Replace the parts at idxs by the (written) parts, and commit: added files & removed files in one version
+    fn swap(&mut self, operation: &str, results: Vec<(Vec<usize>, Vec<DatasetPart>)>) -> Result<()> {
+        let mut replaced = vec![false; self.parts.len()];
+        let mut added = Vec::new();
+        for (idxs, part) in results {
+            for i in idxs {
+                replaced[i] = true;
+            }
+            added.extend(part);
+        }
+        let add = self.part_files(&added);
+        let mut remove = Vec::new();
+        let parts = std::mem::take(&mut self.parts);
+        for (part, replaced) in parts.into_iter().zip(replaced) {
+            if !replaced {
+                self.parts.push(part);
+            } else {
+                remove.extend(self.part_files(std::slice::from_ref(&part)));
+            }
+        }
+        self.parts.extend(added);
+        self.commit(operation, add, remove)
+    }
+
+    // Rewrite the files of every partition (& bucket) into as few files of about target_file_size bytes
+    // (on disk) as possible
+    pub fn compact(&mut self, target_file_size: usize) -> Result<()> {
+        self.compact_with(&CompactOptions::new(target_file_size))
+    }
+
+    // Compaction, sorting the rows of each partition and/or moving them to new buckets. The new files
+    // replace the old ones in a single commit; old files are only deleted after it (unless versioned).
+    pub fn compact_with(&mut self, options: &CompactOptions) -> Result<()> {
+        if options.target_file_size == 0 {
+            return Err(Error::InvalidArgument("Target file size should be positive".to_string()))
+        }
+        let rebucket = options.buckets.is_some() && options.buckets != self.buckets;
+        let spec = if rebucket {options.buckets.as_ref()} else {self.buckets.as_ref()};
+
+        // Files per partition, and per bucket unless rows move to new buckets
+        let mut groups: HashMap<(String, Option<usize>), Vec<usize>> = HashMap::new();
+        for (i, p) in self.parts.iter().enumerate() {
+            let bucket = if rebucket {None} else {p.bucket};
+            groups.entry((p.partition_path(&self.partitions)?, bucket)).or_default().push(i);
+        }
+
+        let results = groups
+            .into_par_iter()
+            .map(|(_, idxs)| {
+                let mut size = 0;
+                for i in &idxs {
+                    let path = self.parts[*i].path.as_ref().ok_or_else(|| Error::InvalidArgument("Only stored parts can be compacted".to_string()))?;
+                    size += fs::metadata(path)?.len() as usize;
+                }
+                let num_files = size.div_ceil(options.target_file_size).max(1);
+                if !rebucket && options.sort_by.is_none() && idxs.len() <= num_files {
+                    return Ok(None)
+                }
+
+                let mut table = self.parts[idxs[0]].read_all()?;
+                for i in &idxs[1..] {
+                    table.append(&mut self.parts[*i].read_all()?)?;
+                }
+                let num_rows = table.num_rows();
+                if num_rows == 0 {
+                    return Ok(Some((idxs, vec![])))
+                }
+                if let Some(columns) = &options.sort_by {
+                    table = table.sort_by(columns, &vec![false; columns.len()], false)?;
+                }
+
+                // Files of about the target size per bucket: the size of the partition is spread by rows
+                let filters = self.parts[idxs[0]].filters.clone();
+                let mut parts = Vec::new();
+                for part in bucketize_on(spec, DatasetPart::new(Some(table), filters.clone(), None))? {
+                    let table = part.read()?;
+                    let bucket_size = size as f64 * table.num_rows() as f64 / num_rows as f64;
+                    let num_files = ((bucket_size / options.target_file_size as f64).ceil() as usize).max(1);
+                    let rows = table.num_rows().div_ceil(num_files);
+                    for offset in (0..table.num_rows()).step_by(rows) {
+                        let mut new = DatasetPart::new(Some(table.slice(offset, rows)), filters.clone(), None).with_bucket(part.bucket);
+                        self.write_part(&mut new)?;
+                        parts.push(new);
+                    }
+                }
+                Ok(Some((idxs, parts)))
+            })
+            .collect::<Result<Vec<Option<(Vec<usize>, Vec<DatasetPart>)>>>>()?;
+
+        let results = results.into_iter().flatten().collect::<Vec<(Vec<usize>, Vec<DatasetPart>)>>();
+        if results.is_empty() {
+            return Ok(())
+        }
+        if rebucket {
+            self.buckets = options.buckets.clone();
+        }
+        self.swap("compact", results)
+    }
+
+    // BUCKETS
+
+    // Rows matching a row of keys on all its columns. Keys need the bucket columns: only the files of the
+    // buckets of the keys are read, and only those of their partitions when keys have the partition columns.
+    pub fn lookup(&self, keys: &Table) -> Result<Table> {
+        let spec = self.buckets.as_ref().ok_or_else(|| Error::InvalidArgument("Lookup needs a bucketed dataset".to_string()))?;
+        let fields = self.schema.clone().ok_or_else(|| Error::InvalidArgument("Lookup needs the schema of the dataset".to_string()))?;
+        let columns = keys.columns().into_iter().cloned().collect::<Vec<String>>();
+        if let Some(column) = spec.columns.iter().find(|c| !columns.contains(c)) {
+            return Err(Error::InvalidArgument(format!("Lookup keys need bucket column {}", column)))
+        }
+        let keys = self.cast_keys(keys)?;
+        let buckets = spec.buckets(&keys)?.into_iter().flatten().collect::<HashSet<usize>>();
+        let partitions = match &self.partitions {
+            Some(partitions) if partitions.iter().all(|p| columns.contains(p)) => Some((partitions, key_values(&keys, partitions)?)),
+            _ => None,
+        };
+
+        let tables = self.parts
+            .par_iter()
+            .filter(|part| match part.bucket {
+                Some(bucket) => buckets.contains(&bucket),
+                None => true,
+            })
+            .filter(|part| match &partitions {
+                Some((partitions, values)) => values.contains(&part.partition_values(partitions)),
+                None => true,
+            })
+            .map(|part| {
+                let table = part.read()?;
+                match table.num_rows() {
+                    0 => Ok(None),
+                    _ => Ok(Some(table.join(&keys, &columns, &columns, JoinType::Semi)?)),
+                }
+            })
+            .collect::<Result<Vec<Option<Table>>>>()?;
+
+        let mut tables = tables.into_iter().flatten().filter(|t| t.num_rows() > 0);
+        let mut result = tables.next().unwrap_or_else(|| Table::empty(fields));
+        for mut table in tables {
+            result.append(&mut table)?;
+        }
+        Ok(result)
+    }
+
+    // Keys with the types of the dataset columns, so they hash and compare as the stored values
+    fn cast_keys(&self, keys: &Table) -> Result<Table> {
+        let schema = match &self.schema {
+            Some(schema) => schema,
+            None => return Ok(keys.clone()),
+        };
+        let fields = keys.fields
+            .iter()
+            .map(|f| schema.iter().find(|s| s.name == f.name).cloned().ok_or_else(|| Error::ColumnNotFound(f.name.clone())))
+            .collect::<Result<Vec<Field>>>()?;
+        let chunks = keys.chunks
+            .iter()
+            .map(|chunk| {
+                let arrays = chunk.arrays()
+                    .iter()
+                    .zip(&fields)
+                    .map(|(array, f)| match array.data_type() == &f.data_type {
+                        true => Ok(array.clone()),
+                        false => Ok(cast(array.as_ref(), &f.data_type, CastOptions::default())?),
+                    })
+                    .collect::<Result<Vec<Box<dyn Array>>>>()?;
+                Ok(Chunk::new(arrays))
+            })
+            .collect::<Result<Vec<Chunk<Box<dyn Array>>>>>()?;
+        Ok(Table::new(fields, chunks))
+    }
+
+    // Join with a dataset bucketed the same way, bucket by bucket: equal keys hash to the same bucket, so
+    // bucket i of one side only needs bucket i of the other and no rows are shuffled. The keys have to pair
+    // the bucket columns of both sides (in the order of the bucket specs).
+    pub fn join_buckets(&self, other: &Dataset, left_on: &[String], right_on: &[String], how: JoinType) -> Result<Table> {
+        let (left, right) = match (&self.buckets, &other.buckets) {
+            (Some(left), Some(right)) => (left, right),
+            _ => return Err(Error::InvalidArgument("Bucket join needs bucketed datasets".to_string())),
+        };
+        let paired = left.columns.len() == right.columns.len() && left.columns.iter().zip(&right.columns).all(|(l, r)| {
+            left_on.iter().zip(right_on).any(|(lo, ro)| lo == l && ro == r)
+        });
+        if left.num_buckets != right.num_buckets || !paired {
+            return Err(Error::InvalidArgument(format!("Bucket specs {:?} and {:?} don't match on keys {:?} / {:?}", left, right, left_on, right_on)))
+        }
+
+        let left_parts = self.bucket_parts(left.num_buckets)?;
+        let right_parts = other.bucket_parts(right.num_buckets)?;
+        let tables = left_parts
+            .into_par_iter()
+            .zip(right_parts)
+            .map(|(l, r)| {
+                let l = read_parts(&l, self.schema.clone().unwrap_or_default())?;
+                let r = read_parts(&r, other.schema.clone().unwrap_or_default())?;
+                l.join(&r, left_on, right_on, how)
+            })
+            .collect::<Result<Vec<Table>>>()?;
+
+        let mut tables = tables.into_iter();
+        let mut result = tables.next().ok_or_else(|| Error::InvalidArgument("Bucket join needs at least one bucket".to_string()))?;
+        for mut table in tables.filter(|t| t.num_rows() > 0) {
+            result.append(&mut table)?;
+        }
+        Ok(result)
+    }
+
+    // Parts per bucket (over all partitions); fails on files without a bucket
+    fn bucket_parts(&self, num_buckets: usize) -> Result<Vec<Vec<&DatasetPart>>> {
+        let mut buckets = vec![Vec::new(); num_buckets];
+        for part in &self.parts {
+            match part.bucket.filter(|b| *b < num_buckets) {
+                Some(bucket) => buckets[bucket].push(part),
+                None => return Err(Error::InvalidArgument(format!("Part {:?} is not in a bucket", part.path))),
+            }
+        }
+        Ok(buckets)
+    }
+
     // IO RELATED
 
-    pub fn from_storage(root: &String, lazy: bool) -> Self {
+    pub fn from_storage(root: &str, lazy: bool) -> Result<Self> {
+        Self::from_storage_with(root, &ReadOptions::default(), lazy)
+    }
+
+    // Dataset with only the parts whose partition values can match predicate
+    pub fn from_storage_where(root: &str, predicate: &FilterExpr, lazy: bool) -> Result<Self> {
+        Self::from_storage_with(root, &ReadOptions { predicate: Some(predicate.clone()), ..Default::default() }, lazy)
+    }
+
+    // Dataset with the parts that can match the predicate of options; loaded parts only hold the
+    // columns and rows selected by options
+    pub fn from_storage_with(root: &str, options: &ReadOptions, lazy: bool) -> Result<Self> {
+        Self::from_log(root, options, lazy, None::<fn(Log) -> Result<Log>>)
+    }
+
+    // Dataset with the parts that were live at version. Files of older versions are only kept
+    // when the storage is versioned.
+    pub fn from_storage_at(root: &str, version: u64, lazy: bool) -> Result<Self> {
+        Self::from_log(root, &ReadOptions::default(), lazy, Some(|log: Log| log.at(version)))
+    }
+
+    // Dataset with the parts that were live at timestamp (milliseconds since epoch)
+    pub fn from_storage_as_of(root: &str, timestamp: u64, lazy: bool) -> Result<Self> {
+        Self::from_log(root, &ReadOptions::default(), lazy, Some(|log: Log| log.at_timestamp(timestamp)))
+    }
+
+    // Read the manifest and resolve the files from the transaction log, as selected by at (the latest
+    // version when None), or from the files under root for datasets without a log
+    fn from_log<F>(root: &str, options: &ReadOptions, lazy: bool, at: Option<F>) -> Result<Self>
+    where F: FnOnce(Log) -> Result<Log> {
         let fpath = format!("{root}/manifest.json");
-        let contents = std::fs::read_to_string(&fpath).expect("Could not read manifest file in given root");
-        let mut obj = serde_json::from_str::<Self>(&contents).expect("Issue in deserialization of manifest");
+        let contents = std::fs::read_to_string(&fpath)?;
+        let mut obj = serde_json::from_str::<Self>(&contents)?;
+
+        let storage = obj.storage.as_ref().ok_or_else(|| Error::Manifest(format!("{} has no storage options", fpath)))?;
+        let (root, format) = (storage.root.clone(), storage.format);
+        let files = if Log::exists(&root) {
+            let log = match at {
+                Some(at) => {
+                    let log = at(Log::read(&root)?)?;
+                    let files = log.files();
+                    if let Some(file) = files.iter().find(|f| !Path::new(&root).join(f).exists()) {
+                        return Err(Error::InvalidArgument(format!("Version {:?} of {} is no longer available, {} was removed", log.version(), root, file)))
+                    }
+                    log
+                },
+                None => Log::read(&root)?,
+            };
+            obj.version = log.version();
+            obj.schema = log.schema().or(obj.schema);
+            obj.partitions = log.partitions();
+            obj.buckets = log.buckets();
+            log.files()
+        } else if at.is_some() {
+            return Err(Error::Manifest(format!("{} has no transaction log", root)))
+        } else {
+            legacy_files(&root, &format)?
+        };
 
         // Lazy load underlying parts
-        let contains = "parquet".to_string();
-        obj.parts = find_parts(&obj.storage.as_ref().unwrap().root, &contains, lazy);
-
-        obj
-    }
-
-    pub fn to_storage(&self) {
-        match &self.storage {
-            Some(storage) => {
-                // Create & clear directory
-                fs::remove_dir_all(&storage.root).ok(); //#.expect("Remove files in root dir failed");
-                fs::create_dir(&storage.root).expect("Create dir failed");
-
-                // Save manifest
-                let path = format!("{}/manifest.json", storage.root);
-                fs::write(
-                    path,
-                    serde_json::to_string_pretty(&self).expect("Issue in serialization of manifest"),
-                )
-                .unwrap();
-
-                // Save underlying parts
-                self.parts
-                    .par_iter()
-                    .map(|p| {
-                        let ppath = p.partition_path(&self.partitions);
-                        match &p.table {
-                            Some(t) => {
-                                // println!("Writing to storage {} with rows: {}", ppath, t.num_rows());
-                                let fpath = Path::new(&storage.root).join(ppath).join("file.parquet").to_str().expect("Path merging failed").to_string();
-                                t.to_parquet(&fpath);
-                            }
-                            None => println!("Table has not been loaded yet!")
-                        }
-                    })
-                    .collect::<()>();
-            },
-            None => println!("Storage options are not set on dataset")
+        obj.parts = find_parts(&root, &files, &obj.schema, &obj.partition_types(), &obj.buckets, options, lazy)?;
+        let kept = obj.part_files(&obj.parts).into_iter().collect::<HashSet<String>>();
+        obj.pruned = files.into_iter().filter(|f| !kept.contains(f)).collect();
+
+        Ok(obj)
+    }
+
+    // Write all parts as new files and replace the current files of the dataset in a single commit
+    #[allow(clippy::wrong_self_convention)] // Parts get their new paths and the dataset the new version
+    pub fn to_storage(&mut self) -> Result<()> {
+        let (version, remove) = self.committed_files()?;
+
+        // Parts loaded with a selection of their file are loaded whole again, the file is replaced
+        let mut parts = std::mem::take(&mut self.parts);
+        let reloaded = parts
+            .par_iter_mut()
+            .filter(|p| p.partial)
+            .map(|p| p.load())
+            .collect::<Result<()>>();
+        if let Err(e) = reloaded {
+            self.parts = parts;
+            return Err(e)
+        }
+
+        // Save underlying parts, one file per bucket of each part for bucketed datasets (parts that already
+        // hold a single bucket are written as they are)
+        if self.buckets.is_some() {
+            let bucketized = parts
+                .into_par_iter()
+                .map(|p| match &p.table {
+                    Some(_) if p.bucket.is_some() => Ok(vec![p]),
+                    Some(_) => self.bucketize(p),
+                    None => Err(Error::InvalidArgument(format!("Part {} has not been loaded", p.partition_path(&self.partitions)?)))
+                })
+                .collect::<Result<Vec<Vec<DatasetPart>>>>()?;
+            parts = bucketized.into_iter().flatten().collect();
+        }
+        let written = parts
+            .par_iter_mut()
+            .map(|p| {
+                match &p.table {
+                    Some(_) => self.write_part(p),
+                    None => Err(Error::InvalidArgument(format!("Part {} has not been loaded", p.partition_path(&self.partitions)?)))
+                }
+            })
+            .collect::<Result<()>>();
+        self.parts = parts;
+        written?;
+
+        // Commit & save manifest (overwrites whatever was committed last)
+        self.version = version;
+        let add = self.part_files(&self.parts);
+        self.commit("write", add, remove)
+    }
+
+    // Write a stream of tables (e.g. from read_parquet_stream) batch by batch and replace the current files
+    // of the dataset in a single commit, like to_storage. Parts are written per batch and not kept in memory.
+    pub fn write_stream<I>(&mut self, tables: I) -> Result<()>
+    where I: Iterator<Item = Result<Table>> {
+        let (version, remove) = self.committed_files()?;
+        let mut parts = Vec::new();
+        for table in tables {
+            for mut part in self.write_table(&table?)? {
+                part.table = None;
+                parts.push(part);
+            }
+        }
+        self.parts = parts;
+        self.version = version;
+        let add = self.part_files(&self.parts);
+        self.commit("write", add, remove)
+    }
+
+    // Version and files under root that a new write replaces: the committed files, or all data files without a log.
+    // Files pruned when opening the dataset were never loaded, so they are kept.
+    fn committed_files(&self) -> Result<(Option<u64>, Vec<String>)> {
+        let storage = self.storage.as_ref().ok_or_else(|| Error::Manifest("Storage options are not set on dataset".to_string()))?;
+        fs::create_dir_all(&storage.root)?;
+        let (version, files) = if Log::exists(&storage.root) {
+            let log = Log::read(&storage.root)?;
+            (log.version(), log.files())
+        } else {
+            (None, legacy_files(&storage.root, &storage.format)?)
+        };
+        Ok((version, files.into_iter().filter(|f| !self.pruned.contains(f)).collect()))
+    }
+
+    // Files (relative to root) of parts
+    fn part_files(&self, parts: &[DatasetPart]) -> Vec<String> {
+        let root = self.storage.as_ref().map(|s| s.root.clone()).unwrap_or_default();
+        parts.iter().filter_map(|p| p.path.as_ref()).map(|path| relative_path(&root, path)).collect()
+    }
+
+    // Commit added & removed files on top of the version this dataset is based on. Without a log yet, the
+    // first commit also adds the files that are already there. The manifest is only replaced once the commit
+    // succeeded, and removed files are deleted after it, unless the storage is versioned.
+    fn commit(&mut self, operation: &str, add: Vec<String>, remove: Vec<String>) -> Result<()> {
+        let storage = self.storage.as_ref().ok_or_else(|| Error::Manifest("Storage options are not set on dataset".to_string()))?;
+        let (version, add) = if Log::exists(&storage.root) {
+            (self.version.map(|v| v + 1).unwrap_or(0), add)
+        } else {
+            let mut files = legacy_files(&storage.root, &storage.format)?
+                .into_iter()
+                .filter(|f| !add.contains(f) && !remove.contains(f))
+                .collect::<Vec<String>>();
+            files.extend(add);
+            (0, files)
+        };
+        let commit = Commit::new(operation, add, remove, self.schema.clone(), self.partitions.clone())
+            .with_buckets(self.buckets.clone());
+        let commit = Log::commit(&storage.root, version, commit)?;
+        self.version = Some(commit.version);
+        self.write_manifest()?;
+
+        if !storage.versioned {
+            for file in &commit.remove {
+                match fs::remove_file(Path::new(&storage.root).join(file)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(Error::Io(e)),
+                    _ => {},
+                }
+            }
         }
+        Ok(())
+    }
+
+    // Replace manifest.json atomically: write a temporary file and rename it
+    fn write_manifest(&self) -> Result<()> {
+        let storage = self.storage.as_ref().ok_or_else(|| Error::Manifest("Storage options are not set on dataset".to_string()))?;
+        fs::create_dir_all(&storage.root)?;
+        let path = Path::new(&storage.root).join("manifest.json");
+        let tmp = Path::new(&storage.root).join(format!(".manifest.{}.tmp", unique_id()));
+        fs::write(&tmp, serde_json::to_string_pretty(&self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use arrow2::{
+        array::{Int32Array, Int64Array, Utf8Array},
+        chunk::Chunk,
+        scalar::{PrimitiveScalar, Utf8Scalar},
+    };
+
+    use super::*;
+    use crate::core::filter::FilterOp;
+    use crate::io::factory::{create_random_table, create_key_table, create_temp_root};
+    use crate::io::parquet::read::read_parquet_stream;
+
+    // Data files in a directory under root
+    fn data_files(root: &str, dir: &str) -> Vec<String> {
+        let mut files = fs::read_dir(Path::new(root).join(dir))
+            .map(|entries| entries.map(|e| e.unwrap().path().to_string_lossy().to_string()).filter(|f| f.ends_with(".parquet")).collect())
+            .unwrap_or_else(|_| Vec::new());
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_partition_roundtrip() {
+        let root = create_temp_root("partition_roundtrip");
+        let fields = vec![Field::new("key", DataType::Utf8, true), Field::new("value", DataType::Int32, true)];
+        let chunk = Chunk::new(vec![
+            Utf8Array::<i32>::from([Some(""), None, Some("a/b"), Some("")]).boxed(),
+            Int32Array::from(&[Some(1), Some(2), Some(3), Some(4)]).boxed(),
+        ]);
+        let table = Table::new(fields, vec![chunk]);
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        table.to_dataset(Some(vec!["key".to_string()]), None, Some(storage)).unwrap().to_storage().unwrap();
+
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        let keys = dataset.parts
+            .iter()
+            .map(|p| p.partition_values(&["key".to_string()]).remove(0))
+            .collect::<HashSet<Value>>();
+        assert_eq!(keys, HashSet::from([Value::Utf8("".to_string()), Value::Null, Value::Utf8("a/b".to_string())]));
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 4);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_malformed_file() {
+        let root = create_temp_root("malformed_file");
+        let table = Table::new(vec![Field::new("value", DataType::Int32, true)], vec![Chunk::new(vec![Int32Array::from(&[Some(1)]).boxed()])]);
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        table.to_dataset(None, None, Some(storage)).unwrap().to_storage().unwrap();
+        fs::write(&data_files(&root, "")[0], "not parquet").unwrap();
+
+        assert!(Dataset::from_storage(&root, true).is_ok());
+        assert!(matches!(Dataset::from_storage(&root, false), Err(Error::Parquet(_))));
+        assert!(matches!(Dataset::from_storage("does/not/exist", true), Err(Error::Io(_))));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_append() {
+        let root = create_temp_root("append");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        create_key_table(&["a", "b"], &[1, 2]).to_dataset(Some(vec!["key".to_string()]), None, Some(storage)).unwrap().to_storage().unwrap();
+        let before = fs::read(&data_files(&root, "key=a")[0]).unwrap();
+
+        let mut dataset = Dataset::from_storage(&root, true).unwrap();
+        dataset.append(&create_key_table(&["a", "c"], &[1, 2])).unwrap();
+        assert_eq!(data_files(&root, "key=a").len(), 2);
+        assert!(data_files(&root, "key=a").iter().any(|f| fs::read(f).unwrap() == before));
+
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.parts.len(), 4);
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 4);
+        let other = Table::new(vec![Field::new("value", DataType::Int32, true)], vec![]);
+        assert!(matches!(Dataset::from_storage(&root, true).unwrap().append(&other), Err(Error::SchemaMismatch(_))));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_upsert_delete() {
+        let root = create_temp_root("upsert_delete");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let partitions = Some(vec!["key".to_string()]);
+        create_key_table(&["a", "a", "b", "c"], &[1, 2, 3, 4]).to_dataset(partitions, None, Some(storage)).unwrap().to_storage().unwrap();
+        let untouched = data_files(&root, "key=b");
+        let replaced = data_files(&root, "key=a");
+
+        let ids = vec!["id".to_string()];
+        let mut dataset = Dataset::from_storage(&root, true).unwrap();
+        dataset.upsert(&create_key_table(&["a", "a", "d"], &[2, 5, 6]), &ids).unwrap();
+        dataset.delete(&create_key_table(&["c", "e"], &[4, 9]), &ids).unwrap();
+        assert_eq!(data_files(&root, "key=b"), untouched);
+        assert!(replaced.iter().all(|f| !Path::new(f).exists()));
+        assert_eq!(Log::read(&root).unwrap().version(), Some(2));
+
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        let rows = dataset.parts
+            .iter()
+            .map(|p| (p.partition_path(&dataset.partitions).unwrap(), p.table.as_ref().unwrap().num_rows()))
+            .collect::<HashMap<String, usize>>();
+        assert_eq!(rows, HashMap::from([("key=a".to_string(), 3), ("key=b".to_string(), 1), ("key=d".to_string(), 1)]));
+
+        // Partitions pruned when opening are merged with when written to, and kept otherwise
+        let predicate = FilterExpr::scalar("key", FilterOp::Equal, Utf8Scalar::<i32>::new(Some("b")));
+        let mut dataset = Dataset::from_storage_where(&root, &predicate, false).unwrap();
+        assert_eq!(dataset.parts.len(), 1);
+        dataset.upsert(&create_key_table(&["a", "b"], &[5, 7]), &ids).unwrap();
+        dataset.delete(&create_key_table(&["d"], &[6]), &ids).unwrap();
+        dataset.to_storage().unwrap();
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        let rows = dataset.parts
+            .iter()
+            .map(|p| (p.partition_path(&dataset.partitions).unwrap(), p.table.as_ref().unwrap().num_rows()))
+            .collect::<HashMap<String, usize>>();
+        assert_eq!(rows, HashMap::from([("key=a".to_string(), 3), ("key=b".to_string(), 2)]));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_uncommitted_files() {
+        let root = create_temp_root("uncommitted_files");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let mut dataset = create_key_table(&["a", "a"], &[1, 2]).to_dataset(None, None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        let first = data_files(&root, "");
+
+        // A file left by a crashed writer is never visible
+        create_key_table(&["a"], &[3]).to_parquet(Path::new(&root).join(unique_file_name(&Format::Parquet, None)).to_string_lossy().as_ref()).unwrap();
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.version, Some(0));
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 2);
+
+        // Rewriting replaces the committed files only, once the new ones are committed
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let mut dataset = create_key_table(&["a"], &[4]).to_dataset(None, None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        assert!(first.iter().all(|f| !Path::new(f).exists()));
+        assert_eq!(data_files(&root, "").len(), 2);
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.version, Some(1));
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 1);
+
+        // A writer losing the race to commit leaves the manifest alone
+        let mut stale = Dataset::from_storage(&root, true).unwrap();
+        Dataset::from_storage(&root, true).unwrap().append(&create_key_table(&["a"], &[5])).unwrap();
+        let manifest = Path::new(&root).join("manifest.json");
+        fs::remove_file(&manifest).unwrap();
+        assert!(matches!(stale.append(&create_key_table(&["a"], &[6])), Err(Error::Manifest(_))));
+        assert!(!manifest.exists());
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_time_travel() {
+        let root = create_temp_root("time_travel");
+        let rows = |dataset: &Dataset| dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>();
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None).with_versioned(true);
+        let mut dataset = create_key_table(&["a", "b"], &[1, 2]).to_dataset(Some(vec!["key".to_string()]), None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        dataset.upsert(&create_key_table(&["a", "c"], &[3, 4]), &["id".to_string()]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        dataset.delete(&create_key_table(&["a", "a"], &[1, 3]), &["id".to_string()]).unwrap();
+
+        let log = Log::read(&root).unwrap();
+        assert_eq!(rows(&Dataset::from_storage(&root, false).unwrap()), 2);
+        assert_eq!(rows(&Dataset::from_storage_at(&root, 0, false).unwrap()), 2);
+        assert_eq!(rows(&Dataset::from_storage_at(&root, 1, false).unwrap()), 4);
+        let dataset = Dataset::from_storage_as_of(&root, log.commits[1].timestamp, false).unwrap();
+        assert_eq!((dataset.version, rows(&dataset)), (Some(1), 4));
+        assert!(matches!(Dataset::from_storage_at(&root, 3, true), Err(Error::InvalidArgument(_))));
+        assert!(matches!(Dataset::from_storage_as_of(&root, 0, true), Err(Error::InvalidArgument(_))));
+
+        // Without versioning the files of older versions are gone
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        create_key_table(&["a"], &[5]).to_dataset(None, None, Some(storage)).unwrap().to_storage().unwrap();
+        assert!(matches!(Dataset::from_storage_at(&root, 2, true), Err(Error::InvalidArgument(_))));
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_partition_pruning() {
+        let root = create_temp_root("partition_pruning");
+        let fields = vec![Field::new("day", DataType::Int32, true), Field::new("id", DataType::Int32, true)];
+        let chunk = Chunk::new(vec![
+            Int32Array::from_slice([1, 1, 2, 3, 4]).boxed(),
+            Int32Array::from_slice([1, 2, 3, 4, 5]).boxed(),
+        ]);
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        Table::new(fields, vec![chunk]).to_dataset(Some(vec!["day".to_string()]), None, Some(storage)).unwrap().to_storage().unwrap();
+        let int = |v: i32| PrimitiveScalar::new(DataType::Int32, Some(v));
+
+        // A corrupt file outside the predicate is never read
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        let day4 = dataset.parts.iter().find(|p| p.partition_values(&["day".to_string()]) == vec![Value::Int(4)]).unwrap();
+        fs::write(day4.path.as_ref().unwrap(), "not parquet").unwrap();
+
+        let dataset = Dataset::from_storage_where(&root, &FilterExpr::between("day", int(1), int(2)), false).unwrap();
+        assert_eq!(dataset.parts.len(), 2);
+        let dataset = Dataset::from_storage_where(&root, &FilterExpr::is_in("day", vec![int(3), int(5)]), false).unwrap();
+        assert_eq!(dataset.parts.len(), 1);
+
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        let predicate = FilterExpr::scalar("day", FilterOp::Less, int(3)).and(FilterExpr::scalar("id", FilterOp::NotEqual, int(2)));
+        assert_eq!(dataset.scan(&predicate).unwrap().num_rows(), 2);
+        assert!(dataset.scan(&FilterExpr::scalar("day", FilterOp::GreaterEqual, int(1))).is_err());
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_read_options() {
+        let root = create_temp_root("read_options");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, Some(Compression::Snappy));
+        create_random_table(3).to_dataset(Some(vec!["c1".to_string()]), None, Some(storage)).unwrap().to_storage().unwrap();
+        let file = &data_files(&root, "c1=0")[0];
+        let metadata = arrow2::io::parquet::read::read_metadata(&mut fs::File::open(file).unwrap()).unwrap();
+        assert_eq!(format!("{:?}", metadata.row_groups[0].columns()[0].compression()), "Snappy");
+
+        let options = ReadOptions {
+            columns: Some(vec!["c4".to_string(), "c1".to_string()]),
+            predicate: Some(FilterExpr::scalar("c4", FilterOp::Less, PrimitiveScalar::new(DataType::Int64, Some(1_000i64)))),
+            row_limit: Some(50),
+            batch_size: Some(10),
+        };
+        let dataset = Dataset::from_storage_with(&root, &options, false).unwrap();
+        assert_eq!(dataset.parts.len(), 10);
+        let table = dataset.parts[0].table.as_ref().unwrap();
+        assert_eq!((table.columns(), table.num_rows(), table.chunks.len()), (vec![&"c4".to_string(), &"c1".to_string()], 50, 5));
+
+        let mut part = Dataset::from_storage(&root, true).unwrap().parts.remove(0);
+        part.load_with(&ReadOptions { row_limit: Some(25), batch_size: Some(10), ..Default::default() }).unwrap();
+        assert_eq!((part.table.as_ref().unwrap().num_rows(), part.table.as_ref().unwrap().chunks.len()), (25, 3));
+
+        let options = ReadOptions { columns: Some(vec!["c9".to_string()]), ..Default::default() };
+        assert!(matches!(Dataset::from_storage_with(&root, &options, false), Err(Error::ColumnNotFound(_))));
+
+        // Writes to a dataset loaded with a predicate keep the rows it left out
+        let predicate = FilterExpr::scalar("c4", FilterOp::Less, PrimitiveScalar::new(DataType::Int64, Some(1_000i64)));
+        let mut dataset = Dataset::from_storage_where(&root, &predicate, false).unwrap();
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 1_000);
+        dataset.upsert(&create_random_table(1).head(&10), &["c4".to_string()]).unwrap();
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 300_000);
+        let mut dataset = Dataset::from_storage_with(&root, &ReadOptions { row_limit: Some(5), ..Default::default() }, false).unwrap();
+        dataset.to_storage().unwrap();
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 300_000);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_append_stream() {
+        let root = create_temp_root("append_stream");
+        let path = std::env::temp_dir().join("arrow_lake_test_append_stream.parquet").to_str().unwrap().to_string();
+        fs::remove_dir_all(&root).ok();
+        create_random_table(2).to_parquet(&path).unwrap();
+
+        let options = ReadOptions { batch_size: Some(30_000), ..Default::default() };
+        let batches = read_parquet_stream(&path, &options).unwrap().map(|t| t.unwrap().num_rows()).collect::<Vec<usize>>();
+        assert_eq!(batches, vec![30_000, 30_000, 30_000, 10_000, 30_000, 30_000, 30_000, 10_000]);
+        let options = ReadOptions { row_limit: Some(50_000), ..options };
+        assert_eq!(read_parquet_stream(&path, &options).unwrap().map(|t| t.unwrap().num_rows()).sum::<usize>(), 50_000);
+
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let mut dataset = Dataset::new(Some(vec!["c1".to_string()]), None, vec![], Some(storage), None);
+        dataset.append_stream(read_parquet_stream(&path, &ReadOptions::default()).unwrap()).unwrap();
+        assert!(dataset.parts.iter().all(|p| p.table.is_none()));
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!((dataset.version, dataset.parts.len()), (Some(0), 40));
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 200_000);
+
+        // Writing a stream replaces what was committed
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let mut dataset = Dataset::new(Some(vec!["c1".to_string()]), None, vec![], Some(storage), None);
+        dataset.write_stream(read_parquet_stream(&path, &options).unwrap()).unwrap();
+        let dataset = Dataset::from_storage(&root, false).unwrap();
+        assert_eq!(dataset.version, Some(1));
+        assert_eq!(dataset.parts.iter().map(|p| p.table.as_ref().unwrap().num_rows()).sum::<usize>(), 50_000);
+        fs::remove_dir_all(&root).ok();
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_ipc_storage() {
+        let root = create_temp_root("ipc_storage");
+        let storage = DatasetStorage::new(root.clone(), Format::Ipc, Some(Compression::Lz4Raw));
+        let mut dataset = create_random_table(1).to_dataset(Some(vec!["c1".to_string()]), None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        dataset.append(&create_random_table(1)).unwrap();
+        assert!(dataset.parts.iter().all(|p| p.path.as_ref().unwrap().ends_with(".arrow")));
+
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!(dataset.parts.len(), 20);
+        let predicate = FilterExpr::scalar("c1", FilterOp::Equal, PrimitiveScalar::new(DataType::Int32, Some(3)))
+            .and(FilterExpr::scalar("c2", FilterOp::Less, PrimitiveScalar::new(DataType::Int32, Some(50_000))));
+        assert_eq!(dataset.scan(&predicate).unwrap().num_rows(), 10_000);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_csv_storage() {
+        let root = create_temp_root("csv_storage");
+        let storage = DatasetStorage::new(root.clone(), Format::Csv, None);
+        let table = create_random_table(1);
+        let mut dataset = table.to_dataset(Some(vec!["c1".to_string()]), None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        assert!(dataset.parts.iter().all(|p| p.path.as_ref().unwrap().ends_with(".csv")));
+
+        // typed by the dataset schema, not inferred
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!(dataset.parts.len(), 10);
+        assert_eq!(dataset.parts[0].read().unwrap().fields, table.fields);
+        let predicate = FilterExpr::scalar("c1", FilterOp::Equal, PrimitiveScalar::new(DataType::Int32, Some(3)))
+            .and(FilterExpr::scalar("c2", FilterOp::Less, PrimitiveScalar::new(DataType::Int32, Some(50_000))));
+        assert_eq!(dataset.scan(&predicate).unwrap().num_rows(), 5_000);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_json_storage() {
+        let root = create_temp_root("json_storage");
+        let path = std::env::temp_dir().join("arrow_lake_test_json_storage.json").to_str().unwrap().to_string();
+        let events = (0..100).map(|i| format!(r#"{{"kind": "k{}", "payload": {{"n": {}, "tags": ["t"]}}}}"#, i % 4, i)).collect::<Vec<String>>();
+        fs::write(&path, events.join("\n")).unwrap();
+
+        let table = Table::from_ndjson(&path, &JsonOptions::default()).unwrap();
+        let storage = DatasetStorage::new(root.clone(), Format::Json, None);
+        let mut dataset = table.to_dataset(Some(vec!["kind".to_string()]), None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        assert!(dataset.parts.iter().all(|p| p.path.as_ref().unwrap().ends_with(".json")));
+
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!(dataset.parts.len(), 4);
+        let predicate = FilterExpr::scalar("kind", FilterOp::Equal, Utf8Scalar::<i32>::new(Some("k1")));
+        let read = dataset.scan(&predicate).unwrap();
+        assert_eq!((&read.fields, read.num_rows()), (&table.fields, 25));
+        fs::remove_dir_all(&root).ok();
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_buckets() {
+        let root = create_temp_root("buckets");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let spec = BucketSpec::new(vec!["c2".to_string()], 4);
+        let mut dataset = create_random_table(1).to_dataset(Some(vec!["c1".to_string()]), Some(spec.clone()), Some(storage)).unwrap();
+        assert_eq!(dataset.parts.len(), 40);
+        dataset.to_storage().unwrap();
+        for part in &dataset.parts {
+            let bucket = part.bucket().unwrap();
+            assert!(part.path.as_ref().unwrap().ends_with(&format!("_{:05}.parquet", bucket)));
+            assert!(spec.buckets(part.table.as_ref().unwrap()).unwrap().iter().flatten().all(|b| *b == bucket));
+        }
+
+        // bucket spec from the log, buckets from the file names
+        dataset.upsert(&create_random_table(1).head(&1_000), &["c2".to_string()]).unwrap();
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!((dataset.buckets.as_ref(), dataset.parts.len()), (Some(&spec), 40));
+        let part = &dataset.parts[0];
+        assert!(spec.buckets(&part.read().unwrap()).unwrap().iter().flatten().all(|b| Some(*b) == part.bucket()));
+        assert_eq!(dataset.parts.iter().map(|p| p.read().unwrap().num_rows()).sum::<usize>(), 100_000);
+        assert_eq!(bucket_from_path(&format!("c1=3/{}", unique_file_name(&Format::Parquet, Some(7)))), Some(7));
+        assert_eq!(bucket_from_path(&unique_file_name(&Format::Parquet, None)), None);
+        assert_eq!(bucket_from_path("c1=3/data_2023.parquet"), None);
+
+        // parts that are already bucketed are written as they are
+        let mut dataset = Dataset::from_storage(&root, false).unwrap();
+        dataset.to_storage().unwrap();
+        assert_eq!(dataset.parts.len(), 40);
+        assert_eq!(Dataset::from_storage(&root, true).unwrap().parts.iter().filter(|p| p.bucket().is_some()).count(), 40);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_lookup_join() {
+        let root = create_temp_root("lookup_join");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let spec = BucketSpec::new(vec!["c2".to_string()], 8);
+        let mut dataset = create_random_table(1).to_dataset(Some(vec!["c1".to_string()]), Some(spec.clone()), Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+
+        // keys typed differently from the dataset; 200_000 is not there
+        let c2 = Int64Array::from_slice([5, 17, 99_999, 200_000]);
+        let c1 = Int64Array::from_slice([5, 7, 9, 0]);
+        let keys = Table::new(
+            vec![Field::new("c1", DataType::Int64, true), Field::new("c2", DataType::Int64, true)],
+            vec![Chunk::new(vec![c1.boxed(), c2.boxed()])],
+        );
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        let needed = spec.buckets(&dataset.cast_keys(&keys).unwrap()).unwrap().concat();
+        let values = key_values(&keys, &["c1".to_string()]).unwrap();
+        // only the files of the buckets & partitions of the keys are read
+        for part in dataset.parts.iter().filter(|p| !needed.contains(&p.bucket().unwrap()) || !values.contains(&p.partition_values(&["c1".to_string()]))) {
+            fs::remove_file(part.path.as_ref().unwrap()).unwrap();
+        }
+        assert_eq!(dataset.lookup(&keys).unwrap().num_rows(), 3);
+        // without the bucket column
+        assert!(matches!(dataset.lookup(&keys.select(&["c1".to_string()]).unwrap()), Err(Error::InvalidArgument(_))));
+        // no key matches: an empty table that can still be used
+        let missing = Table::new(
+            keys.fields.clone(),
+            vec![Chunk::new(vec![Int64Array::from_slice([0]).boxed(), Int64Array::from_slice([200_000]).boxed()])],
+        );
+        let none = dataset.lookup(&missing).unwrap();
+        assert_eq!(none.sort_by(&["c2".to_string()], &[false], false).unwrap().num_rows(), 0);
+        assert_eq!(none.column("c3").unwrap().len(), 0);
+        let mut bare = Dataset::from_storage(&root, true).unwrap();
+        bare.schema = None;
+        assert!(matches!(bare.lookup(&keys), Err(Error::InvalidArgument(_))));
+
+        let root2 = format!("{}_right", root);
+        fs::remove_dir_all(&root2).ok();
+        let storage = DatasetStorage::new(root2.clone(), Format::Parquet, None);
+        let right = create_random_table(1).head(&5_000);
+        let mut other = right.to_dataset(None, Some(spec), Some(storage)).unwrap();
+        other.to_storage().unwrap();
+        let left = create_random_table(1).to_dataset(Some(vec!["c1".to_string()]), dataset.buckets.clone(), None).unwrap();
+        let on = vec!["c2".to_string()];
+        let joined = left.join_buckets(&other, &on, &on, JoinType::Inner).unwrap();
+        assert_eq!((joined.num_rows(), joined.columns().len()), (5_000, 8));
+        assert_eq!(left.join_buckets(&other, &on, &on, JoinType::Left).unwrap().num_rows(), 100_000);
+        assert_eq!(left.join_buckets(&other, &on, &on, JoinType::Anti).unwrap().num_rows(), 95_000);
+        let on3 = vec!["c3".to_string()];
+        assert!(matches!(left.join_buckets(&other, &on3, &on3, JoinType::Inner), Err(Error::InvalidArgument(_))));
+        fs::remove_dir_all(&root).ok();
+        fs::remove_dir_all(&root2).ok();
+    }
+
+    #[test]
+    fn test_compact() {
+        let root = create_temp_root("compact");
+        let storage = DatasetStorage::new(root.clone(), Format::Parquet, None);
+        let mut dataset = create_random_table(1).to_dataset(Some(vec!["c1".to_string()]), None, Some(storage)).unwrap();
+        dataset.to_storage().unwrap();
+        for _ in 0..3 {
+            dataset.append(&create_random_table(1)).unwrap();
+        }
+        assert_eq!((dataset.version, legacy_files(&root, &Format::Parquet).unwrap().len()), (Some(3), 40));
+
+        // one file per partition; the old files are gone after the commit
+        dataset.compact(usize::MAX).unwrap();
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!((dataset.version, dataset.parts.len(), legacy_files(&root, &Format::Parquet).unwrap().len()), (Some(4), 10, 10));
+        assert_eq!(dataset.parts.iter().map(|p| p.read().unwrap().num_rows()).sum::<usize>(), 400_000);
+        // nothing left to compact
+        let mut dataset = dataset;
+        dataset.compact(usize::MAX).unwrap();
+        assert_eq!(dataset.version, Some(4));
+
+        // moved to buckets, then split in smaller sorted files
+        let size = fs::metadata(dataset.parts[0].path.as_ref().unwrap()).unwrap().len() as usize;
+        let spec = BucketSpec::new(vec!["c2".to_string()], 2);
+        dataset.compact_with(&CompactOptions::new(size * 2).with_buckets(spec.clone())).unwrap();
+        let mut dataset = Dataset::from_storage(&root, true).unwrap();
+        assert_eq!((dataset.buckets.as_ref(), dataset.parts.len()), (Some(&spec), 20));
+        dataset.compact_with(&CompactOptions::new(size / 4).with_sort_by(vec!["c4".to_string()])).unwrap();
+        let dataset = Dataset::from_storage(&root, true).unwrap();
+        assert!(dataset.parts.len() >= 40);
+        let part = dataset.parts[0].read().unwrap();
+        let c4 = part.column("c4").unwrap();
+        let c4 = c4.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert!(c4.values().windows(2).all(|w| w[0] <= w[1]));
+        assert!(spec.buckets(&part).unwrap().iter().flatten().all(|b| Some(*b) == dataset.parts[0].bucket()));
+        assert_eq!(dataset.parts.iter().map(|p| p.read().unwrap().num_rows()).sum::<usize>(), 400_000);
+        assert!(matches!(Dataset::from_storage(&root, true).unwrap().compact(0), Err(Error::InvalidArgument(_))));
+        fs::remove_dir_all(&root).ok();
+    }
+}
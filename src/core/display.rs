@@ -0,0 +1,139 @@
+use std::fmt;
+
+use arrow2::{
+    datatypes::DataType,
+    array::{Array, get_value_display},
+};
+
+use crate::core::table::Table;
+
+// Rows shown at the head and at the tail of a table
+const DISPLAY_ROWS: usize = 5;
+// Maximum width (in chars) of a cell
+const DISPLAY_WIDTH: usize = 32;
+
+fn truncate(s: String) -> String {
+    if s.chars().count() > DISPLAY_WIDTH {
+        let mut s = s.chars().take(DISPLAY_WIDTH - 1).collect::<String>();
+        s.push('…');
+        s
+    } else {
+        s
+    }
+}
+
+// Nulls are shown as null, strings are quoted (and escaped) so they can't be confused with nulls
+fn cell(array: &dyn Array, i: usize) -> Result<String, fmt::Error> {
+    if array.is_null(i) {
+        return Ok("null".to_string())
+    }
+    let mut s = String::new();
+    get_value_display(array, "null")(&mut s, i)?;
+    match array.data_type().to_logical_type() {
+        DataType::Utf8 | DataType::LargeUtf8 => Ok(truncate(format!("{:?}", s))),
+        _ => Ok(truncate(s)),
+    }
+}
+
+impl Table {
+    // Chunk and position within the chunk of a row
+    fn locate(&self, row: usize) -> Option<(usize, usize)> {
+        let mut row = row;
+        for (c, chunk) in self.chunks.iter().enumerate() {
+            if row < chunk.len() {
+                return Some((c, row))
+            }
+            row -= chunk.len();
+        }
+        None
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_rows = self.num_rows();
+        let rows = if num_rows > 2 * DISPLAY_ROWS {
+            (0..DISPLAY_ROWS).map(Some)
+                .chain(std::iter::once(None))
+                .chain((num_rows - DISPLAY_ROWS..num_rows).map(Some))
+                .collect::<Vec<Option<usize>>>()
+        } else {
+            (0..num_rows).map(Some).collect()
+        };
+
+        // Header (name and type) followed by the rows, None is the ellipsis row
+        let columns = self.fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let mut column = vec![truncate(field.name.clone()), truncate(format!("{:?}", field.data_type()))];
+                for row in &rows {
+                    column.push(match row {
+                        Some(row) => {
+                            let (c, r) = self.locate(*row).ok_or(fmt::Error)?;
+                            cell(self.chunks[c].columns()[i].as_ref(), r)?
+                        },
+                        None => "…".to_string(),
+                    });
+                }
+                Ok(column)
+            })
+            .collect::<Result<Vec<Vec<String>>, fmt::Error>>()?;
+        let widths = columns
+            .iter()
+            .map(|column| column.iter().map(|s| s.chars().count()).max().unwrap_or(0))
+            .collect::<Vec<usize>>();
+
+        let line = |f: &mut fmt::Formatter<'_>, left: &str, fill: &str, sep: &str, right: &str| -> fmt::Result {
+            let parts = widths.iter().map(|w| fill.repeat(w + 2)).collect::<Vec<String>>();
+            writeln!(f, "{}{}{}", left, parts.join(sep), right)
+        };
+        let row = |f: &mut fmt::Formatter<'_>, i: usize| -> fmt::Result {
+            let parts = columns
+                .iter()
+                .zip(&widths)
+                .map(|(column, w)| format!(" {:<w$} ", column[i], w = w))
+                .collect::<Vec<String>>();
+            writeln!(f, "│{}│", parts.join("┆"))
+        };
+
+        writeln!(f, "shape: ({}, {})", num_rows, self.fields.len())?;
+        line(f, "┌", "─", "┬", "┐")?;
+        row(f, 0)?;
+        row(f, 1)?;
+        line(f, "╞", "═", "╪", "╡")?;
+        for i in 0..rows.len() {
+            row(f, i + 2)?;
+        }
+        line(f, "└", "─", "┴", "┘")?;
+        write!(f, "{} rows", num_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow2::{
+        array::{Int32Array, Utf8Array},
+        chunk::Chunk,
+        datatypes::Field,
+    };
+
+    use super::*;
+    use crate::io::factory::create_random_table;
+
+    #[test]
+    fn test_display() {
+        let fields = vec![Field::new("c1", DataType::Int32, true), Field::new("c2", DataType::Utf8, true)];
+        let chunk = Chunk::new(vec![
+            Int32Array::from(&[Some(1), None]).boxed(),
+            Utf8Array::<i32>::from([Some("null"), Some(&"x".repeat(40))]).boxed(),
+        ]);
+        let shown = Table::new(fields, vec![chunk]).to_string();
+        assert!(shown.contains("│ 1     ┆ \"null\"") && shown.contains("│ null  ┆ \"xxx"));
+        assert!(shown.contains('…') && shown.ends_with("2 rows"));
+
+        let shown = create_random_table(2).to_string();
+        assert_eq!(shown.lines().count(), 2 * DISPLAY_ROWS + 8);
+        assert!(shown.contains("199999"));
+    }
+}
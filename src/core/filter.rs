@@ -1,10 +1,24 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Not;
+
 use arrow2::{
     types::{NativeType},
-    scalar::PrimitiveScalar,
-    array::{PrimitiveArray, BooleanArray},
+    scalar::{Scalar, PrimitiveScalar},
+    datatypes::{DataType, Field},
+    array::{Array, PrimitiveArray, BooleanArray, UInt64Array},
+    bitmap::Bitmap,
+    chunk::Chunk,
     compute::comparison::{eq_scalar, neq_scalar, lt_eq_scalar, lt_scalar, gt_scalar, gt_eq_scalar},
+    compute::comparison,
+    compute::boolean,
+    compute::boolean_kleene,
+    compute::filter::filter_chunk,
+    io::parquet::read::statistics::Statistics,
 };
 
+use crate::core::value::Value;
+use crate::error::{Error, Result};
+
 pub enum FilterPredicate<T: NativeType> {
     LessEqual(PrimitiveScalar<T>),
     Less(PrimitiveScalar<T>),
@@ -25,6 +39,357 @@ pub fn filter_array<T: NativeType>(array: &PrimitiveArray<T>, filter: &FilterPre
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOp {
+    LessEqual,
+    Less,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+// Boolean expression over named columns, evaluated per chunk
+#[derive(Clone, Debug)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Scalar(String, FilterOp, Box<dyn Scalar>), // column <op> literal
+    Column(String, FilterOp, String), // column <op> column
+    In(String, Vec<Box<dyn Scalar>>), // column in (literals)
+}
+
+// Fields & single row chunk of known column values
+type KnownChunk = (Vec<Field>, Chunk<Box<dyn Array>>);
+
+// Result of evaluating an expression on part of the columns. Unknown when it depends on columns without a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tri {
+    True,
+    False,
+    Null,
+    Unknown,
+}
+
+impl Tri {
+    fn from_mask(mask: &BooleanArray) -> Self {
+        match mask.is_null(0) {
+            true => Tri::Null,
+            false if mask.value(0) => Tri::True,
+            false => Tri::False,
+        }
+    }
+}
+
+impl FilterExpr {
+    pub fn scalar<S: Scalar>(column: &str, op: FilterOp, value: S) -> Self {
+        FilterExpr::Scalar(column.to_string(), op, Box::new(value))
+    }
+
+    pub fn column(left: &str, op: FilterOp, right: &str) -> Self {
+        FilterExpr::Column(left.to_string(), op, right.to_string())
+    }
+
+    pub fn is_in<S: Scalar>(column: &str, values: Vec<S>) -> Self {
+        FilterExpr::In(column.to_string(), values.into_iter().map(|v| Box::new(v) as Box<dyn Scalar>).collect())
+    }
+
+    // low <= column <= high
+    pub fn between<S: Scalar>(column: &str, low: S, high: S) -> Self {
+        FilterExpr::scalar(column, FilterOp::GreaterEqual, low).and(FilterExpr::scalar(column, FilterOp::LessEqual, high))
+    }
+
+    pub fn and(self, other: FilterExpr) -> Self {
+        FilterExpr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: FilterExpr) -> Self {
+        FilterExpr::Or(Box::new(self), Box::new(other))
+    }
+
+    // Evaluate to a mask with SQL (Kleene) semantics: comparisons against null are null
+    pub fn evaluate(&self, fields: &[Field], chunk: &Chunk<Box<dyn Array>>) -> Result<BooleanArray> {
+        let mask = match self {
+            FilterExpr::And(l, r) => boolean_kleene::and(&l.evaluate(fields, chunk)?, &r.evaluate(fields, chunk)?),
+            FilterExpr::Or(l, r) => boolean_kleene::or(&l.evaluate(fields, chunk)?, &r.evaluate(fields, chunk)?),
+            FilterExpr::Not(e) => boolean::not(&e.evaluate(fields, chunk)?),
+            FilterExpr::Scalar(column, op, value) => {
+                let array = chunk_column(fields, chunk, column)?;
+                check_types(array.data_type(), value.data_type())?;
+                compare_scalar(array, op, value.as_ref())
+            },
+            FilterExpr::Column(left, op, right) => {
+                let larray = chunk_column(fields, chunk, left)?;
+                let rarray = chunk_column(fields, chunk, right)?;
+                check_types(larray.data_type(), rarray.data_type())?;
+                compare_arrays(larray, op, rarray)
+            },
+            FilterExpr::In(column, values) => {
+                let array = chunk_column(fields, chunk, column)?;
+                let mut mask = BooleanArray::new(DataType::Boolean, Bitmap::new_zeroed(array.len()), None);
+                for value in values {
+                    check_types(array.data_type(), value.data_type())?;
+                    mask = boolean_kleene::or(&mask, &compare_scalar(array, &FilterOp::Equal, value.as_ref()));
+                }
+                mask
+            },
+        };
+        Ok(mask)
+    }
+
+    // Columns referenced by the expression
+    pub fn columns(&self) -> HashSet<String> {
+        match self {
+            FilterExpr::And(l, r) | FilterExpr::Or(l, r) => l.columns().union(&r.columns()).cloned().collect(),
+            FilterExpr::Not(e) => e.columns(),
+            FilterExpr::Scalar(column, _, _) | FilterExpr::In(column, _) => HashSet::from([column.clone()]),
+            FilterExpr::Column(left, _, right) => HashSet::from([left.clone(), right.clone()]),
+        }
+    }
+
+    // Evaluate on known values of some columns (e.g. the partition values of a part), typed by fields.
+    // Some(false) when no row can match, Some(true) when all rows match, None when it depends on other columns.
+    pub fn eval_values(&self, fields: &[Field], values: &HashMap<String, Value>) -> Result<Option<bool>> {
+        let result = match self.eval_tri(fields, values)? {
+            Tri::True => Some(true),
+            Tri::False | Tri::Null => Some(false),
+            Tri::Unknown => None,
+        };
+        Ok(result)
+    }
+
+    fn eval_tri(&self, fields: &[Field], values: &HashMap<String, Value>) -> Result<Tri> {
+        // Single row chunk with the known columns, None when a column has no value
+        let known = |columns: &[&String]| -> Result<Option<KnownChunk>> {
+            let mut known_fields = Vec::new();
+            let mut arrays = Vec::new();
+            for column in columns {
+                match (fields.iter().find(|f| &&f.name == column), values.get(*column)) {
+                    (Some(field), Some(value)) => {
+                        arrays.push(Value::to_array(std::slice::from_ref(value), field.data_type())?);
+                        known_fields.push(field.clone());
+                    },
+                    _ => return Ok(None),
+                }
+            }
+            Ok(Some((known_fields, Chunk::new(arrays))))
+        };
+        let tri = match self {
+            FilterExpr::And(l, r) => match (l.eval_tri(fields, values)?, r.eval_tri(fields, values)?) {
+                (Tri::False, _) | (_, Tri::False) => Tri::False,
+                (Tri::Unknown, _) | (_, Tri::Unknown) => Tri::Unknown,
+                (Tri::True, Tri::True) => Tri::True,
+                _ => Tri::Null,
+            },
+            FilterExpr::Or(l, r) => match (l.eval_tri(fields, values)?, r.eval_tri(fields, values)?) {
+                (Tri::True, _) | (_, Tri::True) => Tri::True,
+                (Tri::Unknown, _) | (_, Tri::Unknown) => Tri::Unknown,
+                (Tri::False, Tri::False) => Tri::False,
+                _ => Tri::Null,
+            },
+            FilterExpr::Not(e) => match e.eval_tri(fields, values)? {
+                Tri::True => Tri::False,
+                Tri::False => Tri::True,
+                tri => tri,
+            },
+            FilterExpr::Scalar(column, _, _) | FilterExpr::In(column, _) => match known(&[column])? {
+                Some((fields, chunk)) => Tri::from_mask(&self.evaluate(&fields, &chunk)?),
+                None => Tri::Unknown,
+            },
+            FilterExpr::Column(left, _, right) => match known(&[left, right])? {
+                Some((fields, chunk)) => Tri::from_mask(&self.evaluate(&fields, &chunk)?),
+                None => Tri::Unknown,
+            },
+        };
+        Ok(tri)
+    }
+}
+
+// Possible outcomes of an expression for the rows of a row group (bit set of true, false & null)
+const MAY_TRUE: u8 = 1;
+const MAY_FALSE: u8 = 2;
+const MAY_NULL: u8 = 4;
+const MAY_ANY: u8 = MAY_TRUE | MAY_FALSE | MAY_NULL;
+
+// Kleene and/or over all combinations of possible outcomes
+fn combine(left: u8, right: u8, and: bool) -> u8 {
+    let outcomes = [MAY_TRUE, MAY_FALSE, MAY_NULL];
+    let mut result = 0;
+    for l in outcomes.iter().filter(|o| left & **o != 0) {
+        for r in outcomes.iter().filter(|o| right & **o != 0) {
+            result |= match (and, *l, *r) {
+                (true, MAY_FALSE, _) | (true, _, MAY_FALSE) => MAY_FALSE,
+                (true, MAY_TRUE, MAY_TRUE) => MAY_TRUE,
+                (false, MAY_TRUE, _) | (false, _, MAY_TRUE) => MAY_TRUE,
+                (false, MAY_FALSE, MAY_FALSE) => MAY_FALSE,
+                _ => MAY_NULL,
+            };
+        }
+    }
+    result
+}
+
+// Per row group comparison of a statistics array with a value, None when the statistic is missing
+fn compare_stats(array: &dyn Array, op: FilterOp, value: &dyn Scalar) -> Vec<Option<bool>> {
+    compare_scalar(array, &op, value).iter().collect()
+}
+
+impl FilterExpr {
+    // Whether each row group can have rows matching the expression, given the statistics of the
+    // referenced columns and the number of rows per row group. Missing statistics never exclude a row group.
+    pub fn may_match(&self, stats: &HashMap<String, Statistics>, num_rows: &[usize]) -> Result<Vec<bool>> {
+        Ok(self.outcomes(stats, num_rows)?.into_iter().map(|o| o & MAY_TRUE != 0).collect())
+    }
+
+    fn outcomes(&self, stats: &HashMap<String, Statistics>, num_rows: &[usize]) -> Result<Vec<u8>> {
+        let outcomes = match self {
+            FilterExpr::And(l, r) | FilterExpr::Or(l, r) => {
+                let and = matches!(self, FilterExpr::And(_, _));
+                l.outcomes(stats, num_rows)?
+                    .into_iter()
+                    .zip(r.outcomes(stats, num_rows)?)
+                    .map(|(l, r)| combine(l, r, and))
+                    .collect()
+            },
+            FilterExpr::Not(e) => e.outcomes(stats, num_rows)?
+                .into_iter()
+                .map(|o| (o & MAY_NULL) | ((o & MAY_TRUE) << 1) | ((o & MAY_FALSE) >> 1))
+                .collect(),
+            FilterExpr::Scalar(column, op, value) => match stats.get(column) {
+                Some(stats) => scalar_outcomes(stats, num_rows, &[(*op, value.as_ref())], false)?,
+                None => vec![MAY_ANY; num_rows.len()],
+            },
+            FilterExpr::In(column, values) => match stats.get(column) {
+                Some(stats) => {
+                    let values = values.iter().map(|v| (FilterOp::Equal, v.as_ref())).collect::<Vec<(FilterOp, &dyn Scalar)>>();
+                    scalar_outcomes(stats, num_rows, &values, true)?
+                },
+                None => vec![MAY_ANY; num_rows.len()],
+            },
+            FilterExpr::Column(_, _, _) => vec![MAY_ANY; num_rows.len()],
+        };
+        Ok(outcomes)
+    }
+}
+
+// Outcomes of column <op> value comparisons from min, max & null count. With any set the comparisons are
+// or-ed (in list), otherwise there is a single comparison.
+fn scalar_outcomes(stats: &Statistics, num_rows: &[usize], comparisons: &[(FilterOp, &dyn Scalar)], any: bool) -> Result<Vec<u8>> {
+    let (min, max) = (stats.min_value.as_ref(), stats.max_value.as_ref());
+    let mut may_true = vec![!any; num_rows.len()];
+    let mut may_false = vec![any; num_rows.len()];
+    for (op, value) in comparisons {
+        check_types(min.data_type(), value.data_type())?;
+        let cmp = |array: &dyn Array, op: FilterOp| compare_stats(array, op, *value);
+        // Some value in [min, max] can make the comparison true (t) or false (f)
+        let (t, f) = match op {
+            FilterOp::Less => (cmp(min, FilterOp::Less), cmp(max, FilterOp::GreaterEqual)),
+            FilterOp::LessEqual => (cmp(min, FilterOp::LessEqual), cmp(max, FilterOp::Greater)),
+            FilterOp::Greater => (cmp(max, FilterOp::Greater), cmp(min, FilterOp::LessEqual)),
+            FilterOp::GreaterEqual => (cmp(max, FilterOp::GreaterEqual), cmp(min, FilterOp::Less)),
+            FilterOp::Equal | FilterOp::NotEqual => {
+                let (min_le, max_ge) = (cmp(min, FilterOp::LessEqual), cmp(max, FilterOp::GreaterEqual));
+                let (min_eq, max_eq) = (cmp(min, FilterOp::Equal), cmp(max, FilterOp::Equal));
+                let contains = min_le.iter().zip(&max_ge).map(|(l, g)| Some(l.unwrap_or(true) && g.unwrap_or(true))).collect::<Vec<Option<bool>>>();
+                let single = min_eq.iter().zip(&max_eq).map(|(l, g)| Some(!(l.unwrap_or(false) && g.unwrap_or(false)))).collect::<Vec<Option<bool>>>();
+                match op {
+                    FilterOp::Equal => (contains, single),
+                    _ => (single, contains),
+                }
+            },
+        };
+        for i in 0..num_rows.len() {
+            let (t, f) = (t[i].unwrap_or(true), f[i].unwrap_or(true));
+            if any {
+                may_true[i] |= t;
+                may_false[i] &= f;
+            } else {
+                may_true[i] = t;
+                may_false[i] = f;
+            }
+        }
+    }
+
+    let null_counts = stats.null_count.as_any().downcast_ref::<UInt64Array>();
+    let outcomes = (0..num_rows.len())
+        .map(|i| {
+            let null_count = null_counts.filter(|c| i < c.len() && c.is_valid(i)).map(|c| c.value(i) as usize);
+            match null_count {
+                Some(nulls) if nulls >= num_rows[i] => MAY_NULL,
+                _ => {
+                    let mut o = 0;
+                    if may_true[i] {o |= MAY_TRUE};
+                    if may_false[i] {o |= MAY_FALSE};
+                    if null_count != Some(0) {o |= MAY_NULL};
+                    o
+                }
+            }
+        })
+        .collect();
+    Ok(outcomes)
+}
+
+impl Not for FilterExpr {
+    type Output = FilterExpr;
+
+    fn not(self) -> Self::Output {
+        FilterExpr::Not(Box::new(self))
+    }
+}
+
+fn chunk_column<'a>(fields: &[Field], chunk: &'a Chunk<Box<dyn Array>>, column: &str) -> Result<&'a dyn Array> {
+    let idx = fields.iter().position(|f| f.name == column).ok_or_else(|| Error::ColumnNotFound(column.to_string()))?;
+    Ok(chunk.columns()[idx].as_ref())
+}
+
+// Comparisons need equal (and comparable) types on both sides
+fn check_types(left: &DataType, right: &DataType) -> Result<()> {
+    if left != right {
+        return Err(Error::SchemaMismatch(format!("Cannot compare {:?} with {:?}", left, right)))
+    }
+    if !comparison::can_eq(left) || !comparison::can_lt(left) {
+        return Err(Error::UnsupportedType(format!("{:?} is not implemented for filtering", left)))
+    }
+    Ok(())
+}
+
+fn compare_scalar(array: &dyn Array, op: &FilterOp, value: &dyn Scalar) -> BooleanArray {
+    match op {
+        FilterOp::LessEqual => comparison::lt_eq_scalar(array, value),
+        FilterOp::Less => comparison::lt_scalar(array, value),
+        FilterOp::Greater => comparison::gt_scalar(array, value),
+        FilterOp::GreaterEqual => comparison::gt_eq_scalar(array, value),
+        FilterOp::Equal => comparison::eq_scalar(array, value),
+        FilterOp::NotEqual => comparison::neq_scalar(array, value),
+    }
+}
+
+fn compare_arrays(left: &dyn Array, op: &FilterOp, right: &dyn Array) -> BooleanArray {
+    match op {
+        FilterOp::LessEqual => comparison::lt_eq(left, right),
+        FilterOp::Less => comparison::lt(left, right),
+        FilterOp::Greater => comparison::gt(left, right),
+        FilterOp::GreaterEqual => comparison::gt_eq(left, right),
+        FilterOp::Equal => comparison::eq(left, right),
+        FilterOp::NotEqual => comparison::neq(left, right),
+    }
+}
+
+// Keep rows where the mask is true; null counts as false
+pub fn filter_chunk_mask(chunk: &Chunk<Box<dyn Array>>, mask: &BooleanArray) -> Result<Chunk<Box<dyn Array>>> {
+    let mask = match mask.validity() {
+        Some(validity) => BooleanArray::new(DataType::Boolean, mask.values() & validity, None),
+        None => mask.clone(),
+    };
+    Ok(filter_chunk(chunk, &mask)?)
+}
+
+pub fn filter_chunk_expr(fields: &[Field], chunk: &Chunk<Box<dyn Array>>, expr: &FilterExpr) -> Result<Chunk<Box<dyn Array>>> {
+    let mask = expr.evaluate(fields, chunk)?;
+    filter_chunk_mask(chunk, &mask)
+}
+
 #[cfg(test)]
 mod tests {
     use arrow2::array::{BooleanArray, Int32Array};
@@ -40,4 +405,58 @@ mod tests {
         
         assert_eq!(filter_arr, BooleanArray::from(&[Some(true), None, Some(false)]));
     }
+
+    #[test]
+    fn test_filter_expr() {
+        let fields = vec![Field::new("a", DataType::Int32, true), Field::new("b", DataType::Int32, true)];
+        let chunk = Chunk::new(vec![
+            Int32Array::from(&[Some(1), None, Some(3), Some(4)]).boxed(),
+            Int32Array::from(&[Some(1), Some(2), Some(2), Some(5)]).boxed(),
+        ]);
+        let expr = FilterExpr::scalar("a", FilterOp::GreaterEqual, PrimitiveScalar::new(DataType::Int32, Some(2)))
+            .or(FilterExpr::column("a", FilterOp::Equal, "b"));
+        assert_eq!(expr.evaluate(&fields, &chunk).unwrap(), BooleanArray::from(&[Some(true), None, Some(true), Some(true)]));
+
+        let filtered = filter_chunk_expr(&fields, &chunk, &!expr).unwrap();
+        assert_eq!(filtered.len(), 0);
+        assert!(matches!(FilterExpr::column("a", FilterOp::Less, "c").evaluate(&fields, &chunk), Err(Error::ColumnNotFound(_))));
+    }
+
+    #[test]
+    fn test_eval_values() {
+        let fields = vec![Field::new("a", DataType::Int32, true), Field::new("b", DataType::Int32, true)];
+        let int = |v: i32| PrimitiveScalar::new(DataType::Int32, Some(v));
+        let values = HashMap::from([("a".to_string(), Value::Int(3))]);
+        let eval = |expr: FilterExpr| expr.eval_values(&fields, &values).unwrap();
+
+        assert_eq!(eval(FilterExpr::between("a", int(1), int(3))), Some(true));
+        assert_eq!(eval(FilterExpr::is_in("a", vec![int(1), int(2)])), Some(false));
+        assert_eq!(eval(FilterExpr::scalar("b", FilterOp::Equal, int(1))), None);
+        assert_eq!(eval(FilterExpr::scalar("b", FilterOp::Equal, int(1)).and(!FilterExpr::is_in("a", vec![int(3)]))), Some(false));
+        assert_eq!(eval(FilterExpr::scalar("b", FilterOp::Equal, int(1)).or(FilterExpr::column("a", FilterOp::Equal, "a"))), Some(true));
+        let values = HashMap::from([("a".to_string(), Value::Null)]);
+        assert_eq!(FilterExpr::scalar("a", FilterOp::NotEqual, int(1)).eval_values(&fields, &values).unwrap(), Some(false));
+    }
+
+    #[test]
+    fn test_may_match() {
+        // Row groups: [1, 3], [5, 5] and [null, null]
+        let stats = Statistics {
+            null_count: UInt64Array::from_slice([0, 1, 2]).boxed(),
+            distinct_count: UInt64Array::from([None, None, None]).boxed(),
+            min_value: Int32Array::from([Some(1), Some(5), None]).boxed(),
+            max_value: Int32Array::from([Some(3), Some(5), None]).boxed(),
+        };
+        let stats = HashMap::from([("a".to_string(), stats)]);
+        let num_rows = [10, 2, 2];
+        let int = |v: i32| PrimitiveScalar::new(DataType::Int32, Some(v));
+        let may_match = |expr: FilterExpr| expr.may_match(&stats, &num_rows).unwrap();
+
+        assert_eq!(may_match(FilterExpr::scalar("a", FilterOp::Equal, int(2))), vec![true, false, false]);
+        assert_eq!(may_match(FilterExpr::scalar("a", FilterOp::NotEqual, int(5))), vec![true, false, false]);
+        assert_eq!(may_match(FilterExpr::scalar("a", FilterOp::Greater, int(3))), vec![false, true, false]);
+        assert_eq!(may_match(FilterExpr::is_in("a", vec![int(0), int(5)])), vec![false, true, false]);
+        assert_eq!(may_match(!FilterExpr::between("a", int(1), int(3))), vec![false, true, false]);
+        assert_eq!(may_match(FilterExpr::scalar("b", FilterOp::Equal, int(2))), vec![true, true, true]);
+    }
 }
@@ -1,19 +1,24 @@
 use std::collections::HashMap;
-use std::cmp::Eq;
 
 use rayon::prelude::*;
 
 use arrow2::{
-    types::NativeType,
     datatypes::*,
     array::{Array, PrimitiveArray},
+    chunk::Chunk,
     compute::take::take,
-    compute::cast::{primitive_to_primitive},
-    compute::arithmetics::basic::{add, add_scalar, mul_scalar},
-    compute::aggregate::{min_primitive, max_primitive},
 };
 
-use crate::core::hm::hashmap_primitive_to_idxs_par;
+use crate::core::hm::{
+    hashmap_primitive_to_idxs_par, hashmap_float_to_idxs, hashmap_utf8_to_idxs, hashmap_boolean_to_idxs, hashmap_binary_to_idxs,
+    hashmap_var_binary_to_idxs, hashmaps_merge,
+};
+use crate::core::value::Value;
+use crate::core::table::Table;
+use crate::core::dataset::DatasetPart;
+use crate::core::chunks::chunk_take;
+use crate::core::agg::{Agg, aggregate_rows};
+use crate::error::{Error, Result};
 
 // fn array_to_dictionary<V: NativeType + Hash + Eq>(array: &dyn Array) -> Result<DictionaryArray<i32>, Error> {
 //     let arr = array.as_any().downcast_ref::<PrimitiveArray<V>>().unwrap();
@@ -22,41 +27,56 @@ use crate::core::hm::hashmap_primitive_to_idxs_par;
 //     primitive_to_dictionary::<V, i32>(arr)
 // }
 
-fn array_to_idxs(array: &dyn Array) -> Result<HashMap<String, Vec<u32>>, String> {
+fn downcast<A: 'static>(array: &dyn Array) -> Result<&A> {
+    array.as_any().downcast_ref::<A>().ok_or_else(|| Error::UnsupportedType(format!("{:?} does not match its array", array.data_type())))
+}
+
+fn array_to_idxs(array: &dyn Array) -> Result<HashMap<Value, Vec<u32>>> {
     match array.data_type() {
-        DataType::Int8   => Ok(hashmap_primitive_to_idxs_par::<i8 >(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::Int16  => Ok(hashmap_primitive_to_idxs_par::<i16>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::Int32  => Ok(hashmap_primitive_to_idxs_par::<i32>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::Int64  => Ok(hashmap_primitive_to_idxs_par::<i64>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),         
-        DataType::UInt8  => Ok(hashmap_primitive_to_idxs_par::<u8 >(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::UInt16 => Ok(hashmap_primitive_to_idxs_par::<u16>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::UInt32 => Ok(hashmap_primitive_to_idxs_par::<u32>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),        
-        DataType::UInt64 => Ok(hashmap_primitive_to_idxs_par::<u64>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),         
-        _ => Err(format!("{:?} is not implemented for hashing", array.data_type()))
+        DataType::Int8   => Ok(hashmap_primitive_to_idxs_par::<i8 >(downcast(array)?)),        
+        DataType::Int16  => Ok(hashmap_primitive_to_idxs_par::<i16>(downcast(array)?)),        
+        DataType::Int32  => Ok(hashmap_primitive_to_idxs_par::<i32>(downcast(array)?)),        
+        DataType::Int64  => Ok(hashmap_primitive_to_idxs_par::<i64>(downcast(array)?)),         
+        DataType::UInt8  => Ok(hashmap_primitive_to_idxs_par::<u8 >(downcast(array)?)),        
+        DataType::UInt16 => Ok(hashmap_primitive_to_idxs_par::<u16>(downcast(array)?)),        
+        DataType::UInt32 => Ok(hashmap_primitive_to_idxs_par::<u32>(downcast(array)?)),        
+        DataType::UInt64 => Ok(hashmap_primitive_to_idxs_par::<u64>(downcast(array)?)),         
+        DataType::Float32 => Ok(hashmap_float_to_idxs::<f32>(downcast(array)?)),
+        DataType::Float64 => Ok(hashmap_float_to_idxs::<f64>(downcast(array)?)),
+        DataType::Date32 | DataType::Time32(_) => Ok(hashmap_primitive_to_idxs_par::<i32>(downcast(array)?)),
+        DataType::Date64 | DataType::Timestamp(_, _) | DataType::Time64(_) | DataType::Duration(_) => Ok(hashmap_primitive_to_idxs_par::<i64>(downcast(array)?)),
+        DataType::Decimal(_, _) => Ok(hashmap_primitive_to_idxs_par::<i128>(downcast(array)?)),
+        DataType::Utf8 => Ok(hashmap_utf8_to_idxs::<i32>(downcast(array)?)),
+        DataType::LargeUtf8 => Ok(hashmap_utf8_to_idxs::<i64>(downcast(array)?)),
+        DataType::Boolean => Ok(hashmap_boolean_to_idxs(downcast(array)?)),
+        DataType::FixedSizeBinary(_) => Ok(hashmap_binary_to_idxs(downcast(array)?)),
+        DataType::Binary => Ok(hashmap_var_binary_to_idxs::<i32>(downcast(array)?)),
+        DataType::LargeBinary => Ok(hashmap_var_binary_to_idxs::<i64>(downcast(array)?)),
+        _ => Err(Error::UnsupportedType(format!("{:?} is not implemented for hashing", array.data_type())))
     }    
 }
 
-pub fn array_take_idxs(array: &dyn Array, idxs: &Vec<u32>) -> Box<dyn Array> {
+pub fn array_take_idxs(array: &dyn Array, idxs: &[u32]) -> Result<Box<dyn Array>> {
     let idxs = PrimitiveArray::from(idxs.iter().map(|x| Some(*x)).collect::<Vec<Option<u32>>>());
-    take(array.as_ref(), &idxs).unwrap()
+    Ok(take(array, &idxs)?)
 }
 
-pub fn hash_group_merge(array: &dyn Array, map: &HashMap<Vec<String>, Vec<u32>>) -> HashMap<Vec<String>, Vec<u32>> {
+pub fn hash_group_merge(array: &dyn Array, map: &HashMap<Vec<Value>, Vec<u32>>) -> Result<HashMap<Vec<Value>, Vec<u32>>> {
     let mapr_iter = map 
         .par_iter()
         .map(|(k1, v1)| {
-            let mapi = array_to_idxs(array_take_idxs(array, v1).as_ref()).unwrap();
-            mapi
+            let mapi = array_to_idxs(array_take_idxs(array, v1)?.as_ref())?;
+            Ok(mapi
                 .into_par_iter()
                 .map(|(k2, v2)| {
                     let mut k3 = k1.clone();
                     k3.push(k2);
-                    let v3 = v2.iter().map(|i| v1[(*i as usize)]).collect();
+                    let v3 = v2.iter().map(|i| v1[*i as usize]).collect();
                     (k3, v3)
                 })
-                .collect::<Vec<(Vec<String>, Vec<u32>)>>()
+                .collect::<Vec<(Vec<Value>, Vec<u32>)>>())
         })
-        .collect::<Vec<Vec<(Vec<String>, Vec<u32>)>>>();
+        .collect::<Result<Vec<Vec<(Vec<Value>, Vec<u32>)>>>>()?;
     
     let mut mapr = HashMap::new();
     for mapi in mapr_iter {
@@ -64,61 +84,121 @@ pub fn hash_group_merge(array: &dyn Array, map: &HashMap<Vec<String>, Vec<u32>>)
             mapr.insert(k, v);
         }
     }
-    mapr
+    Ok(mapr)
 }
 
-pub fn hash_group_merge_recur(arrays: Vec<&dyn Array>, map: &HashMap<Vec<String>, Vec<u32>>) -> HashMap<Vec<String>, Vec<u32>> {
-    if arrays.len() > 0 {
-        let map_new = hash_group_merge(arrays[0], map);
+pub fn hash_group_merge_recur(arrays: Vec<&dyn Array>, map: &HashMap<Vec<Value>, Vec<u32>>) -> Result<HashMap<Vec<Value>, Vec<u32>>> {
+    if !arrays.is_empty() {
+        let map_new = hash_group_merge(arrays[0], map)?;
         hash_group_merge_recur(arrays[1..].to_vec(), &map_new)
     } else {
-        map.clone()
+        Ok(map.clone())
     }
 }
 
 // Currently implementation: 1. groupby first array, 2. for each group in 1, take idxs and groupby array 2, repeat recursively
-pub fn groupby_many(arrays: Vec<&dyn Array>) -> HashMap<Vec<String>, Vec<u32>> {
-    let map1 = array_to_idxs(arrays[0]).unwrap().into_iter().map(|(k, v)| (vec![k], v)).collect::<HashMap<Vec<String>, Vec<u32>>>();
+pub fn groupby_many(arrays: Vec<&dyn Array>) -> Result<HashMap<Vec<Value>, Vec<u32>>> {
+    let first = arrays.first().ok_or_else(|| Error::InvalidArgument("Groupby needs at least one key column".to_string()))?;
+    let map1 = array_to_idxs(*first)?.into_iter().map(|(k, v)| (vec![k], v)).collect::<HashMap<Vec<Value>, Vec<u32>>>();
     if arrays.len() > 1 {
         hash_group_merge_recur(arrays[1..].to_vec(), &map1)
     } else {
-        map1
+        Ok(map1)
     }
 }
 
-// Alternative implementation: 1. group all arrays parallel, 2. merge overlapping indexes for each combination of keys
-fn array_downcast_primitive<T: NativeType + Eq>(array: &dyn Array) -> &PrimitiveArray<T> {
-    array.as_any().downcast_ref::<PrimitiveArray<T>>().expect("Downcast to primitive failed")
-}
+// Key values -> (chunk idx, row idxs) for every chunk containing the key
+type Groups = HashMap<Vec<Value>, Vec<(usize, Vec<u32>)>>;
 
-fn array_to_u64(array: &dyn Array) -> PrimitiveArray<u64> {
-    let arr = match array.data_type() {
-        DataType::Int64  => Ok(array_downcast_primitive::<i64>(array)),               
-        _ => Err(format!("{:?} is not implemented for hashing", array.data_type()))
-    }; 
-    let arr = arr.unwrap(); 
-    let min_value = min_primitive(arr).unwrap();
-    let parr = add_scalar(arr, &min_value);
-    primitive_to_primitive(&parr, &DataType::UInt64)
+// Grouping of a table on key columns; groups are hashed per chunk in parallel and merged by key
+pub struct GroupBy<'a> {
+    table: &'a Table,
+    columns: Vec<String>,
+    idxs: Vec<usize>,
 }
 
-fn arrays_to_hash(arrays: Vec<&dyn Array>) -> PrimitiveArray<u64> {
-    let int_arrays = arrays.into_iter().map(|array| array_to_u64(array)).collect::<Vec<PrimitiveArray<u64>>>();
-    let mut uidxs = int_arrays[0].clone();
-    let mut max_last = max_primitive(&uidxs).unwrap();
-    for iarr in int_arrays[1..].into_iter() {
-        uidxs = mul_scalar(&uidxs, &max_last); // val2 = hash1 * max1 + hash2
-        uidxs = add(&uidxs, iarr);
-        max_last = max_primitive(&uidxs).unwrap();
+impl<'a> GroupBy<'a> {
+    pub fn new(table: &'a Table, columns: &[String]) -> Result<Self> {
+        if columns.is_empty() {
+            return Err(Error::InvalidArgument("Groupby needs at least one key column".to_string()))
+        }
+        let idxs = columns
+            .iter()
+            .map(|column| table.position(column))
+            .collect::<Result<Vec<usize>>>()?;
+        Ok(Self { table, columns: columns.to_vec(), idxs })
     }
-    uidxs
-}
 
-pub fn groupby_many_test(arrays: Vec<&dyn Array>) -> HashMap<Vec<String>, Vec<u32>> {
-    // if arrays.len() == 1 {
-    //     array_to_idxs(arrays[0]).unwrap().into_iter().map(|(k, v)| (vec![k], v)).collect::<HashMap<Vec<String>, Vec<u32>>>()
-    // } else {
-    let idxs = arrays_to_hash(arrays);
-    let map = array_to_idxs(&idxs).unwrap().into_iter().map(|(k, v)| (vec![k], v)).collect::<HashMap<Vec<String>, Vec<u32>>>();
-    map
+    fn groups(&self) -> Result<Groups> {
+        // 1. For loop over row_groups / chunks
+        // 2. Create hash grouping within row_groups
+        // 3. Gather results per key
+        let idxs = &self.idxs;
+        let maps = self.table.chunks
+            .par_iter()
+            .enumerate()
+            .map(|(c, chunk)| {
+                let arrays = idxs.iter().map(|idx| chunk.columns()[*idx].as_ref()).collect::<Vec<&dyn Array>>();
+                Ok(groupby_many(arrays)?
+                    .into_iter()
+                    .map(|(k, v)| (k, (c, v)))
+                    .collect::<HashMap<Vec<Value>, (usize, Vec<u32>)>>())
+            })
+            .collect::<Result<Vec<HashMap<Vec<Value>, (usize, Vec<u32>)>>>>()?;
+        Ok(hashmaps_merge(maps))
+    }
+
+    // Split into one dataset part per group, with the typed key values as filters
+    pub fn parts(&self) -> Result<Vec<DatasetPart>> {
+        self.groups()?
+            .into_par_iter()
+            .map(|(k, v)| {
+                let chunks = v
+                    .iter()
+                    .map(|(c, idxs)| chunk_take(&self.table.chunks[*c], idxs))
+                    .collect::<Result<Vec<Chunk<Box<dyn Array>>>>>()?;
+                let filters = self.columns.clone().into_iter().zip(k).collect::<HashMap<String, Value>>();
+                let table = Table::new(self.table.fields.clone(), chunks);
+                Ok(DatasetPart::new(Some(table), Some(filters), None))
+            })
+            .collect::<Result<Vec<DatasetPart>>>()
+    }
+
+    // One row per group (ordered by key), with the key columns followed by one column per aggregation
+    pub fn agg(&self, aggs: &[Agg]) -> Result<Table> {
+        let mut groups = self.groups()?.into_iter().collect::<Vec<(Vec<Value>, Vec<(usize, Vec<u32>)>)>>();
+        groups.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut fields = self.columns
+            .iter()
+            .map(|column| Ok(self.table.fields[self.table.position(column)?].clone()))
+            .collect::<Result<Vec<Field>>>()?;
+        let mut arrays = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let keys = groups.iter().map(|(k, _)| k[i].clone()).collect::<Vec<Value>>();
+                Value::to_array(&keys, &field.data_type)
+            })
+            .collect::<Result<Vec<Box<dyn Array>>>>()?;
+
+        for agg in aggs {
+            let (input, chunk_arrays) = match &agg.column {
+                Some(column) => {
+                    let idx = self.table.position(column)?;
+                    let chunk_arrays = self.table.chunks.iter().map(|c| c.columns()[idx].as_ref()).collect::<Vec<&dyn Array>>();
+                    (self.table.fields[idx].data_type.clone(), chunk_arrays)
+                },
+                None => (DataType::Null, vec![]),
+            };
+            let data_type = agg.data_type(&input)?;
+            let values = groups
+                .par_iter()
+                .map(|(_, rows)| aggregate_rows(agg, &chunk_arrays, rows))
+                .collect::<Result<Vec<Value>>>()?;
+            arrays.push(Value::to_array(&values, &data_type)?);
+            fields.push(Field::new(agg.name(), data_type, true));
+        }
+        Ok(Table::new(fields, vec![Chunk::new(arrays)]))
+    }
 }
@@ -6,46 +6,76 @@ use std::thread;
 use rayon::prelude::*;
 
 use arrow2::{
-    types::NativeType,
-    array::PrimitiveArray,
+    types::{NativeType, Offset},
+    array::{PrimitiveArray, Utf8Array, BooleanArray, BinaryArray, FixedSizeBinaryArray},
 };
 
-pub fn hashmap_to_str<K>(map: &HashMap<Option<K>, Vec<u32>>) -> HashMap<String, Vec<u32>> where K: std::fmt::Display {
-    let mut map2 = HashMap::new();
-    for (key, value) in map {
-        match key {
-            Some(key) => map2.insert(key.to_string(), value.clone()),
-            None => map2.insert("".to_string(), value.clone())
-        };
-    }
-    map2
+use crate::core::value::Value;
+
+pub fn hashmap_to_value<K>(map: HashMap<Option<K>, Vec<u32>>) -> HashMap<Value, Vec<u32>> where K: Into<Value> {
+    map
+        .into_iter()
+        .map(|(key, value)| {
+            match key {
+                Some(key) => (key.into(), value),
+                None => (Value::Null, value)
+            }
+        })
+        .collect()
 }
 
-pub fn hashmap_primitive_to_idxs<V: NativeType + Eq + Hash>(array: &PrimitiveArray<V>) -> HashMap<String, Vec<u32>> {
+// Group row idxs (starting at offset) by value
+fn hashmap_iter_to_idxs<K: Hash + Eq, I: Iterator<Item = Option<K>>>(iter: I, offset: usize) -> HashMap<Option<K>, Vec<u32>> {
     let mut map = HashMap::new();
-    for (i, a) in array.iter().enumerate() {
+    for (i, a) in iter.enumerate() {
         let vec = map.entry(a).or_insert(Vec::new());
-        vec.push(i as u32);                    
+        vec.push((offset + i) as u32);
     }
-    hashmap_to_str(&map)
+    map
 }
 
-pub fn hashmap_primitive_to_idxs_par<V: NativeType + Eq + Hash>(array: &PrimitiveArray<V>) -> HashMap<String, Vec<u32>> {
-    let num_cpu: usize = thread::available_parallelism().unwrap().get();
+pub fn hashmap_primitive_to_idxs<V: NativeType + Eq + Hash + Into<Value>>(array: &PrimitiveArray<V>, offset: usize) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter().map(|a| a.cloned()), offset))
+}
+
+pub fn hashmap_primitive_to_idxs_par<V: NativeType + Eq + Hash + Into<Value>>(array: &PrimitiveArray<V>) -> HashMap<Value, Vec<u32>> {
+    let num_cpu: usize = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
     if array.len() > 5_000 {
-        let size = array.len() / num_cpu;
+        let size = array.len() / num_cpu + 1;
         let maps = (0..num_cpu)
             .into_par_iter()
             .map(|i| {
-                hashmap_primitive_to_idxs(&array.slice(i * size, min(size, array.len() - i * size)))
+                let offset = min(i * size, array.len());
+                hashmap_primitive_to_idxs(&array.slice(offset, min(size, array.len() - offset)), offset)
             })
-            .collect::<Vec<HashMap<String, Vec<u32>>>>();
+            .collect::<Vec<HashMap<Value, Vec<u32>>>>();
         hashmaps_merge_vec(maps)
     } else {
-        hashmap_primitive_to_idxs(array)
+        hashmap_primitive_to_idxs(array, 0)
     }
 }
 
+pub fn hashmap_utf8_to_idxs<O: Offset>(array: &Utf8Array<O>) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter(), 0))
+}
+
+pub fn hashmap_boolean_to_idxs(array: &BooleanArray) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter(), 0))
+}
+
+pub fn hashmap_binary_to_idxs(array: &FixedSizeBinaryArray) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter(), 0))
+}
+
+pub fn hashmap_var_binary_to_idxs<O: Offset>(array: &BinaryArray<O>) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter(), 0))
+}
+
+// Floats are neither Eq nor Hash, group them by Value (which compares them by total order)
+pub fn hashmap_float_to_idxs<V: NativeType + Into<Value>>(array: &PrimitiveArray<V>) -> HashMap<Value, Vec<u32>> {
+    hashmap_to_value(hashmap_iter_to_idxs(array.iter().map(|a| a.map(|v| (*v).into())), 0))
+}
+
 // Vectors of hashmaps
 pub fn hashmaps_merge<K: Hash + Eq, V>(maps: Vec<HashMap<K, V>>) -> HashMap<K, Vec<V>> {
     let mut map = HashMap::new();
@@ -58,7 +88,7 @@ pub fn hashmaps_merge<K: Hash + Eq, V>(maps: Vec<HashMap<K, V>>) -> HashMap<K, V
     map
 }
 
-pub fn hashmaps_merge_vec<V>(maps: Vec<HashMap<String, Vec<V>>>) -> HashMap<String, Vec<V>> {
+pub fn hashmaps_merge_vec<K: Hash + Eq, V>(maps: Vec<HashMap<K, Vec<V>>>) -> HashMap<K, Vec<V>> {
     let mut map = HashMap::new();
     for mapc in maps.into_iter() {
         for (k, mut v2) in mapc.into_iter() {
@@ -68,4 +98,4 @@ pub fn hashmaps_merge_vec<V>(maps: Vec<HashMap<String, Vec<V>>>) -> HashMap<Stri
         }
     }
     map
-}
\ No newline at end of file
+}
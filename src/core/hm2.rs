@@ -41,16 +41,15 @@ pub fn hashmaps_merge_vec_test<V: NativeType + Eq + Hash>(maps: Vec<HashMap<Opti
     let mut keys = HashSet::new();
     for map in &maps {
         let new_keys = &map.keys().collect::<HashSet<&Option<V>>>();
-        keys = keys.union(new_keys).map(|x| *x).collect::<HashSet<&Option<V>>>();
+        keys = keys.union(new_keys).copied().collect::<HashSet<&Option<V>>>();
     }
     let map = keys
         .into_par_iter()
         .map(|k| {
             let mut vec = Vec::new();
             for map in &maps {
-                let vm = map.get(&k);
-                if vm.is_some() {
-                    let mut vmc = vm.unwrap().clone();
+                if let Some(vm) = map.get(k) {
+                    let mut vmc = vm.clone();
                     vec.append(&mut vmc)
                 }
             }
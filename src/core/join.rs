@@ -0,0 +1,144 @@
+use std::cmp::min;
+
+use rayon::prelude::*;
+
+use arrow2::array::Array;
+
+use crate::core::keys::arrays_to_codes;
+use crate::error::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Semi, // Left rows with a match
+    Anti, // Left rows without a match
+}
+
+// Rows where none of the key columns is null (null keys never match)
+fn valid_rows(arrays: &[Box<dyn Array>]) -> Vec<bool> {
+    let mut valid = vec![true; arrays.first().map_or(0, |a| a.len())];
+    for array in arrays {
+        if array.null_count() > 0 {
+            for (i, v) in valid.iter_mut().enumerate() {
+                *v = *v && array.is_valid(i);
+            }
+        }
+    }
+    valid
+}
+
+// Build phase: right rows per key code, stored as offsets into one vector of row idxs
+fn build_right(codes: &[u64], valid: &[bool], cardinality: usize) -> (Vec<usize>, Vec<u32>) {
+    let mut offsets = vec![0; cardinality + 1];
+    for (code, v) in codes.iter().zip(valid) {
+        if *v {offsets[*code as usize + 1] += 1};
+    }
+    for i in 0..cardinality {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut next = offsets.clone();
+    let mut rows = vec![0; offsets[cardinality]];
+    for (i, (code, v)) in codes.iter().zip(valid).enumerate() {
+        if *v {
+            rows[next[*code as usize]] = i as u32;
+            next[*code as usize] += 1;
+        }
+    }
+    (offsets, rows)
+}
+
+// Left idxs and right idxs of the joined rows; None stands for a missing row (null filled)
+type JoinIdxs = (Vec<Option<u32>>, Vec<Option<u32>>);
+
+pub fn join_arrays(left: &[Box<dyn Array>], right: &[Box<dyn Array>], how: JoinType) -> Result<JoinIdxs> {
+    let columns = left
+        .iter()
+        .zip(right.iter())
+        .map(|(l, r)| vec![l.as_ref(), r.as_ref()])
+        .collect::<Vec<Vec<&dyn Array>>>();
+    let (mut codes, cardinality) = arrays_to_codes(&columns)?;
+    let right_codes = codes.pop().unwrap();
+    let left_codes = codes.pop().unwrap();
+    let left_valid = valid_rows(left);
+    let right_valid = valid_rows(right);
+
+    // Build phase on right side
+    let (offsets, rows) = build_right(&right_codes, &right_valid, cardinality as usize);
+    let matches = |i: usize| -> &[u32] {
+        if left_valid[i] {
+            let code = left_codes[i] as usize;
+            &rows[offsets[code]..offsets[code + 1]]
+        } else {
+            &[]
+        }
+    };
+
+    // Probe phase on left side
+    let workers = rayon::current_num_threads();
+    let size = left_codes.len() / workers + 1;
+    let pairs = (0..workers)
+        .into_par_iter()
+        .map(|w| {
+            let start = min(w * size, left_codes.len());
+            let end = min(start + size, left_codes.len());
+            let mut pairs = Vec::new();
+            for i in start..end {
+                let m = matches(i);
+                match how {
+                    JoinType::Semi => if !m.is_empty() {pairs.push((Some(i as u32), None))},
+                    JoinType::Anti => if m.is_empty() {pairs.push((Some(i as u32), None))},
+                    JoinType::Left | JoinType::Full if m.is_empty() => pairs.push((Some(i as u32), None)),
+                    _ => pairs.extend(m.iter().map(|r| (Some(i as u32), Some(*r)))),
+                }
+            }
+            pairs
+        })
+        .collect::<Vec<Vec<(Option<u32>, Option<u32>)>>>();
+    let (mut left_idxs, mut right_idxs): (Vec<Option<u32>>, Vec<Option<u32>>) = pairs.into_iter().flatten().unzip();
+
+    // Right rows without a match on the left
+    if how == JoinType::Right || how == JoinType::Full {
+        let mut matched = vec![false; cardinality as usize];
+        for (code, v) in left_codes.iter().zip(&left_valid) {
+            if *v {matched[*code as usize] = true};
+        }
+        for (i, (code, v)) in right_codes.iter().zip(&right_valid).enumerate() {
+            if !*v || !matched[*code as usize] {
+                left_idxs.push(None);
+                right_idxs.push(Some(i as u32));
+            }
+        }
+    }
+    Ok((left_idxs, right_idxs))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow2::array::{Int32Array, Utf8Array};
+    use super::*;
+
+    #[test]
+    fn test_join_types() {
+        let left = vec![
+            Int32Array::from(&[Some(1), Some(2), Some(2), None]).boxed(),
+            Utf8Array::<i32>::from([Some("a"), Some("b"), Some("b"), Some("c")]).boxed(),
+        ];
+        let right = vec![
+            Int32Array::from(&[Some(2), Some(2), None, Some(3)]).boxed(),
+            Utf8Array::<i32>::from([Some("b"), Some("b"), Some("c"), Some("d")]).boxed(),
+        ];
+        let (l, r) = join_arrays(&left, &right, JoinType::Inner).unwrap();
+        assert_eq!(l, vec![Some(1), Some(1), Some(2), Some(2)]);
+        assert_eq!(r, vec![Some(0), Some(1), Some(0), Some(1)]);
+
+        let (l, r) = join_arrays(&left, &right, JoinType::Full).unwrap();
+        assert_eq!(l, vec![Some(0), Some(1), Some(1), Some(2), Some(2), Some(3), None, None]);
+        assert_eq!(r, vec![None, Some(0), Some(1), Some(0), Some(1), None, Some(2), Some(3)]);
+
+        assert_eq!(join_arrays(&left, &right, JoinType::Semi).unwrap().0, vec![Some(1), Some(2)]);
+        assert_eq!(join_arrays(&left, &right, JoinType::Anti).unwrap().0, vec![Some(0), Some(3)]);
+    }
+}
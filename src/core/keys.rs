@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::cmp::Eq;
+
+use arrow2::{
+    types::{NativeType, Offset},
+    datatypes::*,
+    array::{Array, PrimitiveArray, Utf8Array, BooleanArray, FixedSizeBinaryArray},
+};
+
+use crate::error::{Error, Result};
+
+// Key codes: every distinct key value is mapped to a dense u64 code, shared over all arrays given.
+// Code 0 is reserved for null, so null keys only match other null keys.
+
+fn array_downcast_primitive<T: NativeType + Eq>(array: &dyn Array) -> &PrimitiveArray<T> {
+    array.as_any().downcast_ref::<PrimitiveArray<T>>().expect("Downcast to primitive failed")
+}
+
+fn primitive_to_codes<T: NativeType + Eq + Hash>(arrays: &[&dyn Array]) -> (Vec<Vec<u64>>, u64) {
+    let mut map: HashMap<T, u64> = HashMap::new();
+    let codes = arrays
+        .iter()
+        .map(|array| {
+            array_downcast_primitive::<T>(*array)
+                .iter()
+                .map(|v| match v {
+                    Some(v) => {
+                        let code = map.len() as u64 + 1;
+                        *map.entry(*v).or_insert(code)
+                    },
+                    None => 0,
+                })
+                .collect::<Vec<u64>>()
+        })
+        .collect::<Vec<Vec<u64>>>();
+    (codes, map.len() as u64 + 1)
+}
+
+fn utf8_to_codes<O: Offset>(arrays: &[&dyn Array]) -> (Vec<Vec<u64>>, u64) {
+    let mut map: HashMap<&str, u64> = HashMap::new();
+    let codes = arrays
+        .iter()
+        .map(|array| {
+            array.as_any().downcast_ref::<Utf8Array<O>>().expect("Downcast to utf8 failed")
+                .iter()
+                .map(|v| match v {
+                    Some(v) => {
+                        let code = map.len() as u64 + 1;
+                        *map.entry(v).or_insert(code)
+                    },
+                    None => 0,
+                })
+                .collect::<Vec<u64>>()
+        })
+        .collect::<Vec<Vec<u64>>>();
+    (codes, map.len() as u64 + 1)
+}
+
+fn binary_to_codes(arrays: &[&dyn Array]) -> (Vec<Vec<u64>>, u64) {
+    let mut map: HashMap<&[u8], u64> = HashMap::new();
+    let codes = arrays
+        .iter()
+        .map(|array| {
+            array.as_any().downcast_ref::<FixedSizeBinaryArray>().expect("Downcast to binary failed")
+                .iter()
+                .map(|v| match v {
+                    Some(v) => {
+                        let code = map.len() as u64 + 1;
+                        *map.entry(v).or_insert(code)
+                    },
+                    None => 0,
+                })
+                .collect::<Vec<u64>>()
+        })
+        .collect::<Vec<Vec<u64>>>();
+    (codes, map.len() as u64 + 1)
+}
+
+fn boolean_to_codes(arrays: &[&dyn Array]) -> (Vec<Vec<u64>>, u64) {
+    let codes = arrays
+        .iter()
+        .map(|array| {
+            array.as_any().downcast_ref::<BooleanArray>().expect("Downcast to boolean failed")
+                .iter()
+                .map(|v| match v {
+                    Some(v) => v as u64 + 1,
+                    None => 0,
+                })
+                .collect::<Vec<u64>>()
+        })
+        .collect::<Vec<Vec<u64>>>();
+    (codes, 3)
+}
+
+// Codes for a single key column, given as one array per side (e.g. left & right table)
+pub fn array_to_codes(arrays: &[&dyn Array]) -> Result<(Vec<Vec<u64>>, u64)> {
+    let data_type = arrays[0].data_type();
+    if let Some(other) = arrays.iter().find(|a| a.data_type() != data_type) {
+        return Err(Error::SchemaMismatch(format!("Key types {:?} and {:?} do not match", data_type, other.data_type())))
+    }
+    match data_type {
+        DataType::Int8   => Ok(primitive_to_codes::<i8 >(arrays)),
+        DataType::Int16  => Ok(primitive_to_codes::<i16>(arrays)),
+        DataType::Int32  => Ok(primitive_to_codes::<i32>(arrays)),
+        DataType::Int64  => Ok(primitive_to_codes::<i64>(arrays)),
+        DataType::UInt8  => Ok(primitive_to_codes::<u8 >(arrays)),
+        DataType::UInt16 => Ok(primitive_to_codes::<u16>(arrays)),
+        DataType::UInt32 => Ok(primitive_to_codes::<u32>(arrays)),
+        DataType::UInt64 => Ok(primitive_to_codes::<u64>(arrays)),
+        DataType::Date32 => Ok(primitive_to_codes::<i32>(arrays)),
+        DataType::Date64 => Ok(primitive_to_codes::<i64>(arrays)),
+        DataType::Timestamp(_, _) => Ok(primitive_to_codes::<i64>(arrays)),
+        DataType::Decimal(_, _) => Ok(primitive_to_codes::<i128>(arrays)),
+        DataType::Utf8 => Ok(utf8_to_codes::<i32>(arrays)),
+        DataType::LargeUtf8 => Ok(utf8_to_codes::<i64>(arrays)),
+        DataType::Boolean => Ok(boolean_to_codes(arrays)),
+        DataType::FixedSizeBinary(_) => Ok(binary_to_codes(arrays)),
+        _ => Err(Error::UnsupportedType(format!("{:?} is not implemented for hashing", data_type)))
+    }
+}
+
+// Combine codes of two columns into dense codes of the pair, so composite keys cannot collide
+fn codes_combine(left: Vec<Vec<u64>>, right: Vec<Vec<u64>>) -> (Vec<Vec<u64>>, u64) {
+    let mut map: HashMap<(u64, u64), u64> = HashMap::new();
+    let codes = left
+        .into_iter()
+        .zip(right)
+        .map(|(lcodes, rcodes)| {
+            lcodes
+                .into_iter()
+                .zip(rcodes)
+                .map(|pair| {
+                    let code = map.len() as u64;
+                    *map.entry(pair).or_insert(code)
+                })
+                .collect::<Vec<u64>>()
+        })
+        .collect::<Vec<Vec<u64>>>();
+    (codes, map.len() as u64)
+}
+
+// Codes for (composite) keys: columns[i][s] is key column i on side s
+pub fn arrays_to_codes(columns: &[Vec<&dyn Array>]) -> Result<(Vec<Vec<u64>>, u64)> {
+    let (first, rest) = columns.split_first().ok_or_else(|| Error::InvalidArgument("Keys need at least one column".to_string()))?;
+    let (mut codes, mut cardinality) = array_to_codes(first)?;
+    for column in rest {
+        let (next, _) = array_to_codes(column)?;
+        (codes, cardinality) = codes_combine(codes, next);
+    }
+    Ok((codes, cardinality))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow2::array::{Int32Array, Int64Array, Utf8Array, BooleanArray};
+    use super::*;
+
+    #[test]
+    fn test_composite_codes() {
+        // (1, 12) and (11, 2) would collide when keys are concatenated or summed
+        let l1 = Int32Array::from(&[Some(1), Some(11), None, None]);
+        let l2 = Int64Array::from(&[Some(12), Some(2), Some(2), None]);
+        let r1 = Int32Array::from(&[Some(11), None, Some(1)]);
+        let r2 = Int64Array::from(&[Some(2), None, Some(2)]);
+        let columns = vec![vec![&l1 as &dyn Array, &r1], vec![&l2 as &dyn Array, &r2]];
+        let (codes, cardinality) = arrays_to_codes(&columns).unwrap();
+
+        assert_eq!(cardinality, 5);
+        assert_eq!(codes[0], vec![0, 1, 2, 3]);
+        assert_eq!(codes[1], vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn test_string_bool_codes() {
+        // Empty string and null are different keys
+        let l1 = Utf8Array::<i64>::from([Some("a"), Some(""), None]);
+        let l2 = BooleanArray::from(&[Some(true), Some(false), Some(false)]);
+        let r1 = Utf8Array::<i64>::from([None, Some("a"), Some("")]);
+        let r2 = BooleanArray::from(&[Some(false), Some(true), Some(true)]);
+        let columns = vec![vec![&l1 as &dyn Array, &r1], vec![&l2 as &dyn Array, &r2]];
+        let (codes, _) = arrays_to_codes(&columns).unwrap();
+
+        assert_eq!(codes[0], vec![0, 1, 2]);
+        assert_eq!(codes[1], vec![2, 0, 3]);
+    }
+}
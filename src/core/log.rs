@@ -0,0 +1,166 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+
+use arrow2::datatypes::Field;
+
+use crate::core::bucket::BucketSpec;
+use crate::error::{Error, Result};
+
+// Transaction log: every change to a dataset is one commit file _log/<version>.json under the root.
+// A commit only becomes visible once its file exists, so files written by a crashed writer are never read.
+pub const LOG_DIR: &str = "_log";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Commit {
+    pub version: u64,
+    pub timestamp: u64, // Milliseconds since epoch
+    pub operation: String,
+    pub add: Vec<String>, // Files (relative to root) added
+    pub remove: Vec<String>, // Files (relative to root) removed
+    pub schema: Option<Vec<Field>>,
+    pub partitions: Option<Vec<String>>,
+    #[serde(default)]
+    pub buckets: Option<BucketSpec>,
+}
+
+impl Commit {
+    pub fn new(operation: &str, add: Vec<String>, remove: Vec<String>, schema: Option<Vec<Field>>, partitions: Option<Vec<String>>) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        Self { version: 0, timestamp, operation: operation.to_string(), add, remove, schema, partitions, buckets: None }
+    }
+
+    pub fn with_buckets(mut self, buckets: Option<BucketSpec>) -> Self {
+        self.buckets = buckets;
+        self
+    }
+}
+
+pub struct Log {
+    root: String,
+    pub commits: Vec<Commit>,
+}
+
+fn log_dir(root: &str) -> PathBuf {
+    Path::new(root).join(LOG_DIR)
+}
+
+fn commit_path(root: &str, version: u64) -> PathBuf {
+    log_dir(root).join(format!("{:020}.json", version))
+}
+
+impl Log {
+    pub fn exists(root: &str) -> bool {
+        log_dir(root).is_dir()
+    }
+
+    // Read all commits, ordered by version
+    pub fn read(root: &str) -> Result<Self> {
+        let mut commits = Vec::new();
+        if Self::exists(root) {
+            for entry in fs::read_dir(log_dir(root))? {
+                let path = entry?.path();
+                let is_commit = path.extension().map(|e| e == "json").unwrap_or(false)
+                    && path.file_stem().map(|s| s.to_string_lossy().chars().all(|c| c.is_ascii_digit())).unwrap_or(false);
+                if is_commit {
+                    let commit = serde_json::from_str::<Commit>(&fs::read_to_string(&path)?)?;
+                    commits.push(commit);
+                }
+            }
+        }
+        commits.sort_by_key(|c| c.version);
+        Ok(Self { root: root.to_string(), commits })
+    }
+
+    pub fn version(&self) -> Option<u64> {
+        self.commits.last().map(|c| c.version)
+    }
+
+    // Replay the commits: files currently in the dataset, in order of addition
+    pub fn files(&self) -> Vec<String> {
+        let mut files: Vec<String> = Vec::new();
+        for commit in &self.commits {
+            files.retain(|f| !commit.remove.contains(f));
+            files.extend(commit.add.iter().cloned());
+        }
+        files
+    }
+
+    // Log as it was at version (commits after it are dropped)
+    pub fn at(mut self, version: u64) -> Result<Self> {
+        if !self.commits.iter().any(|c| c.version == version) {
+            return Err(Error::InvalidArgument(format!("Version {} not found in the log of {}", version, self.root)))
+        }
+        self.commits.retain(|c| c.version <= version);
+        Ok(self)
+    }
+
+    // Log as it was at timestamp (milliseconds since epoch): the commits made at or before it
+    pub fn at_timestamp(mut self, timestamp: u64) -> Result<Self> {
+        self.commits.retain(|c| c.timestamp <= timestamp);
+        if self.commits.is_empty() {
+            return Err(Error::InvalidArgument(format!("No version of {} committed at or before {}", self.root, timestamp)))
+        }
+        Ok(self)
+    }
+
+    pub fn schema(&self) -> Option<Vec<Field>> {
+        self.commits.iter().rev().find_map(|c| c.schema.clone())
+    }
+
+    pub fn partitions(&self) -> Option<Vec<String>> {
+        self.commits.last().and_then(|c| c.partitions.clone())
+    }
+
+    pub fn buckets(&self) -> Option<BucketSpec> {
+        self.commits.last().and_then(|c| c.buckets.clone())
+    }
+
+    // Write commit as version; fails when another writer committed this version first.
+    // The commit is written to a temporary file and linked into place, so it appears complete or not at all.
+    pub fn commit(root: &str, version: u64, mut commit: Commit) -> Result<Commit> {
+        commit.version = version;
+        fs::create_dir_all(log_dir(root))?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        let tmp = log_dir(root).join(format!(".{:020}.json.{}-{}.tmp", version, std::process::id(), nanos));
+        fs::write(&tmp, serde_json::to_string_pretty(&commit)?)?;
+        let linked = fs::hard_link(&tmp, commit_path(root, version));
+        fs::remove_file(&tmp)?;
+        match linked {
+            Ok(_) => Ok(commit),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(Error::Manifest(format!("Version {} was already committed by another writer", version)))
+            },
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_replay() {
+        let root = std::env::temp_dir().join("arrow_lake_test_log_replay").to_str().unwrap().to_string();
+        fs::remove_dir_all(&root).ok();
+        let files = |v: &[&str]| v.iter().map(|f| f.to_string()).collect::<Vec<String>>();
+        Log::commit(&root, 0, Commit::new("write", files(&["a", "b"]), vec![], None, None)).unwrap();
+        Log::commit(&root, 1, Commit::new("upsert", files(&["c"]), files(&["a"]), None, None)).unwrap();
+        assert!(matches!(Log::commit(&root, 1, Commit::new("append", files(&["d"]), vec![], None, None)), Err(Error::Manifest(_))));
+
+        let log = Log::read(&root).unwrap();
+        assert_eq!(log.version(), Some(1));
+        assert_eq!(log.files(), files(&["b", "c"]));
+
+        let timestamp = log.commits[0].timestamp;
+        assert_eq!(Log::read(&root).unwrap().at(0).unwrap().files(), files(&["a", "b"]));
+        assert!(matches!(Log::read(&root).unwrap().at(2), Err(Error::InvalidArgument(_))));
+        assert!(Log::read(&root).unwrap().at_timestamp(timestamp).unwrap().version() <= Some(1));
+        assert!(matches!(Log::read(&root).unwrap().at_timestamp(timestamp - 1), Err(Error::InvalidArgument(_))));
+        fs::remove_dir_all(&root).ok();
+    }
+}
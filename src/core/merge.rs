@@ -1,117 +1,78 @@
-use std::collections::HashMap;
-use std::cmp::{min, Eq};
-
 use rayon::prelude::*;
 
-use arrow2::{
-    types::NativeType,
-    datatypes::*,
-    array::{Array, PrimitiveArray},
-    compute::arithmetics::basic::{add, add_scalar, mul_scalar},
-    compute::aggregate::{min_primitive, max_primitive},
-};
-
-use crate::core::hm2::{hashmap_primitive_to_idx_par};
+use arrow2::array::Array;
 
-fn array_to_idx(array: &dyn Array) -> Result<HashMap<Option<i64>, u32>, String> {
-    match array.data_type() {
-        DataType::Int64  => Ok(hashmap_primitive_to_idx_par::<i64>(array.as_any().downcast_ref().expect("Downcast to primitive failed"))),         
-        _ => Err(format!("{:?} is not implemented for hashing", array.data_type()))
-    }    
-}
+use crate::core::keys::arrays_to_codes;
+use crate::error::Result;
 
-fn array_downcast_primitive<T: NativeType + Eq>(array: &dyn Array) -> &PrimitiveArray<T> {
-    array.as_any().downcast_ref::<PrimitiveArray<T>>().expect("Downcast to primitive failed")
-}
+// Codes of the (composite) key on both sides, with a lookup from code to the last right row holding it
+fn prepare_arrays(left: &[Box<dyn Array>], right: &[Box<dyn Array>]) -> Result<(Vec<u64>, Vec<Option<u32>>)> {
+    let columns = left
+        .iter()
+        .zip(right.iter())
+        .map(|(l, r)| vec![l.as_ref(), r.as_ref()])
+        .collect::<Vec<Vec<&dyn Array>>>();
+    let (mut codes, cardinality) = arrays_to_codes(&columns)?;
+    let right_codes = codes.pop().unwrap();
+    let left_codes = codes.pop().unwrap();
 
-fn array_to_u64(array: &dyn Array) -> PrimitiveArray<i64> {
-    let arr = match array.data_type() {
-        DataType::Int64  => Ok(array_downcast_primitive::<i64>(array)),               
-        _ => Err(format!("{:?} is not implemented for hashing", array.data_type()))
-    }; 
-    let arr = arr.unwrap(); 
-    let min_value = min_primitive(arr).unwrap();
-    let parr = add_scalar(arr, &min_value);
-    parr
-    //primitive_to_primitive(&parr, &DataType::Int64)
+    // Build map on right side (last occurrence wins)
+    let mut right_map = vec![None; cardinality as usize];
+    for (i, code) in right_codes.iter().enumerate() {
+        right_map[*code as usize] = Some(i as u32);
+    }
+    Ok((left_codes, right_map))
 }
 
-fn arrays_to_hash(arrays: &Vec<Box<dyn Array>>) -> PrimitiveArray<i64> {
-    let int_arrays = arrays.into_iter().map(|array| array_to_u64(array.as_ref())).collect::<Vec<PrimitiveArray<i64>>>();
-    let mut uidxs = int_arrays[0].clone();
-    let mut max_last = max_primitive(&uidxs).unwrap();
-    for iarr in int_arrays[1..].into_iter() {
-        uidxs = mul_scalar(&uidxs, &max_last); // val2 = hash1 * max1 + hash2
-        uidxs = add(&uidxs, iarr);
-        max_last = max_primitive(&uidxs).unwrap();
-    }
-    uidxs
+// Left idxs which are not present in right
+fn probe_left(left_codes: &[u64], right_map: &[Option<u32>]) -> Vec<u32> {
+    left_codes
+        .par_iter()
+        .enumerate()
+        .filter(|(_i, code)| right_map[**code as usize].is_none())
+        .map(|(i, _code)| i as u32)
+        .collect::<Vec<u32>>()
 }
 
 // Return left idxs and right idxs which forms a unique table
-fn arrays_to_array(arrays: &Vec<Box<dyn Array>>) -> &PrimitiveArray<i64> {
-    if arrays.len() == 1 {
-        array_downcast_primitive::<i64>(arrays[0].as_ref())
-    } else {
-        array_downcast_primitive::<i64>(arrays[0].as_ref())
-        //TODO: arrays_to_hash(arrays)
-    }
-}
+pub fn merge_arrays(left: &[Box<dyn Array>], right: &[Box<dyn Array>]) -> Result<(Vec<u32>, Vec<u32>)> {
+    // Prepare arrays
+    let (left_codes, right_map) = prepare_arrays(left, right)?;
 
-pub fn arrays_to_idx(arrays: &Vec<Box<dyn Array>>) -> Result<HashMap<Option<i64>, u32>, String> {
-    let array = arrays_to_array(arrays);
-    array_to_idx(array)
-}
+    // Loop over left side: keep left rows whose key is not in right_map
+    let left_idxs = probe_left(&left_codes, &right_map);
 
-fn prepare_arrays<'a>(left: &'a Vec<Box<dyn Array>>, right: &Vec<Box<dyn Array>>) -> (&'a PrimitiveArray<i64>, HashMap<Option<i64>, u32>) {
-    // Build map on right side
-    let right_map = arrays_to_idx(right).unwrap();
-    // Prepare probe on left side
-    let left_array = arrays_to_array(left);
-    (left_array, right_map)
+    let mut right_idxs = right_map.into_iter().flatten().collect::<Vec<u32>>();
+    right_idxs.sort_unstable();
+    Ok((left_idxs, right_idxs))
 }
 
-pub fn merge_arrays(left: &Vec<Box<dyn Array>>, right: &Vec<Box<dyn Array>>) -> (Vec<u32>, Vec<u32>) {
+pub fn delete_arrays(left: &[Box<dyn Array>], right: &[Box<dyn Array>]) -> Result<Vec<u32>> {
     // Prepare arrays
-    let (left_array, right_map) = prepare_arrays(left, right);
+    let (left_codes, right_map) = prepare_arrays(left, right)?;
 
-    // Loop over left side: keep boolean mask of left side, set to true when value not in right_map
-    let workers = 24;
-    let size = left_array.len() / workers + 1;
-    let left_idxs = (0..workers)
-        .into_par_iter()
-        .map(|i| {
-            left_array.slice(i * size, min(size, left_array.len() - i * size))
-                .iter()
-                .enumerate()
-                .filter(|(_i, lv)| right_map.get(&lv.cloned()).is_none())
-                .map(|(i, _lv)| i as u32)
-                .collect::<Vec<u32>>()
-        })
-        .collect::<Vec<Vec<u32>>>();
-    let left_idxs = left_idxs.into_iter().flat_map(|m| m.into_iter()).collect::<Vec<u32>>();
+    // Loop over left side: keep left rows whose key is not in right_map
+    Ok(probe_left(&left_codes, &right_map))
+}
 
-    let right_idxs = right_map.into_values().collect::<Vec<u32>>();
-    (left_idxs, right_idxs)
-} 
+#[cfg(test)]
+mod tests {
+    use arrow2::array::{Int32Array, Int64Array};
+    use super::*;
 
-pub fn delete_arrays(left: &Vec<Box<dyn Array>>, right: &Vec<Box<dyn Array>>) -> Vec<u32> {
-    // Prepare arrays
-    let (left_array, right_map) = prepare_arrays(left, right);
-
-    // Loop over left side: keep boolean mask of left side, set to true when value not in right_map
-    let workers = 24;
-    let size = left_array.len() / workers + 1;
-    let left_idxs = (0..workers)
-        .into_par_iter()
-        .map(|i| {
-            left_array.slice(i * size, min(size, left_array.len() - i * size))
-                .iter()
-                .enumerate()
-                .filter(|(_i, lv)| right_map.get(&lv.cloned()).is_none())
-                .map(|(i, _lv)| i as u32)
-                .collect::<Vec<u32>>()
-        })
-        .collect::<Vec<Vec<u32>>>();
-    left_idxs.into_iter().flat_map(|m| m.into_iter()).collect::<Vec<u32>>()
-}
\ No newline at end of file
+    #[test]
+    fn test_merge_composite() {
+        let left = vec![
+            Int32Array::from(&[Some(1), Some(1), Some(2), None]).boxed(),
+            Int64Array::from(&[Some(1), Some(2), Some(1), Some(1)]).boxed(),
+        ];
+        let right = vec![
+            Int32Array::from(&[Some(1), None, Some(1)]).boxed(),
+            Int64Array::from(&[Some(2), Some(1), Some(2)]).boxed(),
+        ];
+        let (left_idxs, right_idxs) = merge_arrays(&left, &right).unwrap();
+        assert_eq!(left_idxs, vec![0, 2]);
+        assert_eq!(right_idxs, vec![1, 2]);
+        assert_eq!(delete_arrays(&left, &right).unwrap(), vec![0, 2]);
+    }
+}
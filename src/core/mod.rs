@@ -5,4 +5,12 @@ pub mod hm2;
 pub mod groupby;
 pub mod chunks;
 pub mod merge;
-pub mod filter;
\ No newline at end of file
+pub mod filter;
+pub mod keys;
+pub mod value;
+pub mod agg;
+pub mod join;
+pub mod sort;
+pub mod display;
+pub mod log;
+pub mod bucket;
@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use rayon::prelude::*;
+
+use arrow2::{
+    datatypes::*,
+    array::{Array, BinaryArray, FixedSizeBinaryArray, PrimitiveArray},
+    array::ord::{build_compare, DynComparator},
+    chunk::Chunk,
+};
+
+use crate::core::chunks::chunk_take;
+use crate::error::{Error, Result};
+
+// Compare temporal types on their physical values and fixed size binaries as binaries,
+// so every type read from parquet can be ordered
+fn normalize(array: &dyn Array) -> Box<dyn Array> {
+    match array.data_type().to_logical_type() {
+        DataType::Date32 | DataType::Time32(_) => {
+            let array = array.as_any().downcast_ref::<PrimitiveArray<i32>>().expect("Downcast to primitive failed");
+            array.clone().to(DataType::Int32).boxed()
+        },
+        DataType::Date64 | DataType::Time64(_) | DataType::Timestamp(_, _) | DataType::Duration(_) => {
+            let array = array.as_any().downcast_ref::<PrimitiveArray<i64>>().expect("Downcast to primitive failed");
+            array.clone().to(DataType::Int64).boxed()
+        },
+        DataType::FixedSizeBinary(_) => {
+            let array = array.as_any().downcast_ref::<FixedSizeBinaryArray>().expect("Downcast to binary failed");
+            BinaryArray::<i32>::from_iter(array.iter()).boxed()
+        },
+        _ => array.to_boxed()
+    }
+}
+
+// Comparator of two rows of an array; nulls go first or last regardless of the direction
+fn build_row_compare(array: &dyn Array, descending: bool, nulls_first: bool) -> Result<DynComparator> {
+    let array = normalize(array);
+    let cmp = build_compare(array.as_ref(), array.as_ref()).map_err(|e| Error::UnsupportedType(e.to_string()))?;
+    let validity = array.validity().cloned();
+    let null_order = if nulls_first {Ordering::Less} else {Ordering::Greater};
+    Ok(Box::new(move |a, b| {
+        if let Some(validity) = &validity {
+            match (validity.get_bit(a), validity.get_bit(b)) {
+                (false, false) => return Ordering::Equal,
+                (false, true) => return null_order,
+                (true, false) => return null_order.reverse(),
+                _ => {},
+            }
+        }
+        let order = cmp(a, b);
+        if descending {order.reverse()} else {order}
+    }))
+}
+
+// Lexicographic comparator over multiple key arrays
+pub fn build_lex_compare(arrays: &[&dyn Array], descending: &[bool], nulls_first: bool) -> Result<impl Fn(usize, usize) -> Ordering> {
+    let comparators = arrays
+        .iter()
+        .zip(descending)
+        .map(|(array, desc)| build_row_compare(*array, *desc, nulls_first))
+        .collect::<Result<Vec<DynComparator>>>()?;
+    Ok(move |a, b| {
+        for cmp in &comparators {
+            match cmp(a, b) {
+                Ordering::Equal => continue,
+                other => return other,
+            }
+        }
+        Ordering::Equal
+    })
+}
+
+// Stable sort of a chunk on the key columns at idxs
+pub fn sort_chunk(chunk: &Chunk<Box<dyn Array>>, idxs: &[usize], descending: &[bool], nulls_first: bool) -> Result<Chunk<Box<dyn Array>>> {
+    let arrays = idxs.iter().map(|i| chunk.columns()[*i].as_ref()).collect::<Vec<&dyn Array>>();
+    let cmp = build_lex_compare(&arrays, descending, nulls_first)?;
+    let mut order = (0..chunk.len() as u32).collect::<Vec<u32>>();
+    order.sort_by(|a, b| cmp(*a as usize, *b as usize));
+    chunk_take(chunk, &order)
+}
+
+struct Cursor<'a> {
+    pos: usize,
+    end: usize,
+    cmp: &'a dyn Fn(usize, usize) -> Ordering,
+}
+
+impl PartialEq for Cursor<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Cursor<'_> {}
+
+impl PartialOrd for Cursor<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed (BinaryHeap is a max heap); ties are broken on position, which keeps the merge stable
+impl Ord for Cursor<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.cmp)(self.pos, other.pos).then(self.pos.cmp(&other.pos)).reverse()
+    }
+}
+
+// K-way merge of sorted runs [offsets[i], offsets[i + 1]) given a comparator on global row idxs
+pub fn merge_sorted(offsets: &[usize], cmp: &dyn Fn(usize, usize) -> Ordering) -> Vec<u32> {
+    let mut heap = offsets
+        .windows(2)
+        .filter(|w| w[0] < w[1])
+        .map(|w| Cursor { pos: w[0], end: w[1], cmp })
+        .collect::<BinaryHeap<Cursor>>();
+    let mut order = Vec::with_capacity(*offsets.last().unwrap_or(&0));
+    while let Some(mut cursor) = heap.pop() {
+        order.push(cursor.pos as u32);
+        cursor.pos += 1;
+        if cursor.pos < cursor.end {
+            heap.push(cursor);
+        }
+    }
+    order
+}
+
+// Sorted chunks and the order in which to merge their rows
+type SortedChunks = (Vec<Chunk<Box<dyn Array>>>, Vec<u32>);
+
+// Sort every chunk in parallel, then merge the sorted chunks
+pub fn sort_chunks(chunks: &[Chunk<Box<dyn Array>>], idxs: &[usize], descending: &[bool], nulls_first: bool) -> Result<SortedChunks> {
+    let sorted = chunks
+        .par_iter()
+        .map(|chunk| sort_chunk(chunk, idxs, descending, nulls_first))
+        .collect::<Result<Vec<Chunk<Box<dyn Array>>>>>()?;
+
+    let mut offsets = vec![0];
+    for chunk in &sorted {
+        offsets.push(offsets.last().unwrap() + chunk.len());
+    }
+    let keys = idxs
+        .iter()
+        .map(|i| {
+            let arrays = sorted.iter().map(|c| c.columns()[*i].as_ref()).collect::<Vec<&dyn Array>>();
+            arrow2::compute::concatenate::concatenate(&arrays)
+        })
+        .collect::<arrow2::error::Result<Vec<Box<dyn Array>>>>()?;
+    let keys = keys.iter().map(|a| a.as_ref()).collect::<Vec<&dyn Array>>();
+    let cmp = build_lex_compare(&keys, descending, nulls_first)?;
+    let order = merge_sorted(&offsets, &cmp);
+    Ok((sorted, order))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow2::array::{Int32Array, Utf8Array};
+    use super::*;
+
+    #[test]
+    fn test_sort_chunks() {
+        let c1 = Chunk::new(vec![
+            Int32Array::from(&[Some(2), None, Some(1)]).boxed(),
+            Utf8Array::<i32>::from([Some("b"), Some("a"), Some("c")]).boxed(),
+        ]);
+        let c2 = Chunk::new(vec![
+            Int32Array::from(&[Some(2), Some(3), None]).boxed(),
+            Utf8Array::<i32>::from([Some("a"), Some("d"), Some("e")]).boxed(),
+        ]);
+        let (sorted, order) = sort_chunks(&[c1, c2], &[0, 1], &[true, false], false).unwrap();
+        assert_eq!(sorted[0].columns()[1], Utf8Array::<i32>::from([Some("b"), Some("c"), Some("a")]).boxed());
+        // 3/d, 2/a, 2/b, 1/c, null/a, null/e
+        assert_eq!(order, vec![3, 4, 0, 1, 2, 5]);
+    }
+}
@@ -1,20 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use rayon::prelude::*;
 
 use arrow2::{
     datatypes::Field,
-    array::{Array, PrimitiveArray},
+    array::{Array, PrimitiveArray, new_empty_array},
     chunk::Chunk,
     compute::concatenate::concatenate,
     compute::take::take,
 };
 
-use crate::core::hm::{hashmaps_merge};
-use crate::core::groupby::{groupby_many};
-use crate::core::chunks::{chunk_take, chunk_head};
-use crate::core::dataset::{DatasetPart, Dataset, DatasetStorage};
+use crate::core::groupby::GroupBy;
+use crate::core::chunks::chunk_head;
+use crate::core::dataset::{Dataset, DatasetStorage, Compression};
+use crate::core::bucket::BucketSpec;
 use crate::core::merge::{merge_arrays, delete_arrays};
-use crate::io::parquet::write::write_parquet;
+use crate::core::filter::{FilterExpr, filter_chunk_expr};
+use crate::core::join::{JoinType, join_arrays};
+use crate::core::sort::sort_chunks;
+use crate::io::parquet::write::{write_parquet, WriteOptions};
+use crate::io::ipc::read::read_ipc;
+use crate::io::ipc::write::write_ipc;
+use crate::io::csv::CsvOptions;
+use crate::io::csv::read::read_csv;
+use crate::io::csv::write::write_csv;
+use crate::io::json::JsonOptions;
+use crate::io::json::read::read_ndjson;
+use crate::io::json::write::write_ndjson;
+use crate::error::{Error, Result};
 
 #[derive(Clone)]
 pub struct Table {
@@ -27,6 +39,12 @@ impl Table {
         Table { fields, chunks }
     }
 
+    // Table without rows, holding a single empty chunk (so column based operations still apply)
+    pub fn empty(fields: Vec<Field>) -> Table {
+        let arrays = fields.iter().map(|f| new_empty_array(f.data_type.clone())).collect();
+        Table { fields, chunks: vec![Chunk::new(arrays)] }
+    }
+
     pub fn columns(&self) -> Vec<&String> {
         self.fields.iter().map(|f| &f.name).collect()
     }
@@ -37,11 +55,11 @@ impl Table {
     pub fn len(&self) -> usize {self.num_rows()}
 
     pub fn head(&self, n: &usize) -> Table {
-        let mut remaining = n.clone();
+        let mut remaining = *n;
         let mut new_chunks = Vec::new();
         for chunk in &self.chunks {
             if chunk.len() > remaining {
-                new_chunks.push(chunk_head(&chunk, &remaining));
+                new_chunks.push(chunk_head(chunk, &remaining));
                 remaining = 0
             } else {
                 new_chunks.push(chunk.clone());
@@ -52,149 +70,346 @@ impl Table {
         Self { fields:self.fields.clone(), chunks:new_chunks }
     }
 
-    pub fn table_eq(&self, other: &Table) {
-        assert_eq!(self.fields, other.fields);
+    // Rows offset..offset+length (as far as there are rows)
+    pub fn slice(&self, offset: usize, length: usize) -> Table {
+        let (mut skip, mut remaining) = (offset, length);
+        let mut new_chunks = Vec::new();
+        for chunk in &self.chunks {
+            if remaining == 0 {break};
+            if skip >= chunk.len() {
+                skip -= chunk.len();
+                continue
+            }
+            let n = remaining.min(chunk.len() - skip);
+            new_chunks.push(Chunk::new(chunk.arrays().iter().map(|a| a.slice(skip, n)).collect()));
+            skip = 0;
+            remaining -= n;
+        }
+        Self { fields:self.fields.clone(), chunks:new_chunks }
     }
 
-    pub fn position(&self, column: &String) -> usize {
-        self.columns().iter().position(|&r| r == column).unwrap()
+    pub fn table_eq(&self, other: &Table) -> Result<()> {
+        if self.fields != other.fields {
+            return Err(Error::SchemaMismatch(format!("{:?} and {:?} differ", self.columns(), other.columns())))
+        }
+        Ok(())
+    }
+
+    pub fn position(&self, column: &str) -> Result<usize> {
+        self.columns().iter().position(|&r| r == column).ok_or_else(|| Error::ColumnNotFound(column.to_string()))
     }
  
-    pub fn column(&self, column: &String) -> Box<dyn Array> {
-        let idx = self.position(column);
-        let arrays = self.chunks
+    fn positions(&self, columns: &[String]) -> Result<Vec<usize>> {
+        columns.iter().map(|column| self.position(column)).collect()
+    }
+
+    // Column names should be unique and every chunk should match the fields
+    fn validate(&self) -> Result<()> {
+        for (i, field) in self.fields.iter().enumerate() {
+            if self.fields[..i].iter().any(|f| f.name == field.name) {
+                return Err(Error::SchemaMismatch(format!("Duplicate column {}", field.name)))
+            }
+        }
+        for chunk in &self.chunks {
+            if chunk.columns().len() != self.fields.len() {
+                return Err(Error::SchemaMismatch(format!("Chunk has {} columns, schema has {}", chunk.columns().len(), self.fields.len())))
+            }
+            for (array, field) in chunk.columns().iter().zip(&self.fields) {
+                if array.data_type() != field.data_type() {
+                    return Err(Error::SchemaMismatch(format!("Column {} is {:?}, expected {:?}", field.name, array.data_type(), field.data_type())))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn project(&self, idxs: &[usize]) -> Result<Self> {
+        let fields = idxs.iter().map(|i| self.fields[*i].clone()).collect::<Vec<Field>>();
+        let chunks = self.chunks
+            .iter()
+            .map(|chunk| Chunk::new(idxs.iter().map(|i| chunk.columns()[*i].clone()).collect()))
+            .collect::<Vec<Chunk<Box<dyn Array>>>>();
+        let table = Self { fields, chunks };
+        table.validate()?;
+        Ok(table)
+    }
+
+    pub fn select(&self, columns: &[String]) -> Result<Self> {
+        self.project(&self.positions(columns)?)
+    }
+
+    pub fn drop(&self, columns: &[String]) -> Result<Self> {
+        let drop = self.positions(columns)?;
+        let idxs = (0..self.fields.len()).filter(|i| !drop.contains(i)).collect::<Vec<usize>>();
+        self.project(&idxs)
+    }
+
+    // Same columns in a new order; every column should be given
+    pub fn reorder(&self, columns: &[String]) -> Result<Self> {
+        if columns.len() != self.fields.len() {
+            return Err(Error::InvalidArgument("Reorder should contain every column".to_string()))
+        }
+        self.project(&self.positions(columns)?)
+    }
+
+    // Rename columns (old name -> new name)
+    pub fn rename(&self, columns: &HashMap<String, String>) -> Result<Self> {
+        self.positions(&columns.keys().cloned().collect::<Vec<String>>())?;
+        let fields = self.fields
+            .iter()
+            .map(|f| match columns.get(&f.name) {
+                Some(name) => Field { name: name.clone(), ..f.clone() },
+                None => f.clone(),
+            })
+            .collect::<Vec<Field>>();
+        let table = Self { fields, chunks: self.chunks.clone() };
+        table.validate()?;
+        Ok(table)
+    }
+
+    // Add a column, or replace it if the name exists; the array is sliced along the chunks
+    pub fn with_column(&self, name: &str, array: Box<dyn Array>) -> Result<Self> {
+        if array.len() != self.num_rows() {
+            return Err(Error::SchemaMismatch(format!("Column {} has {} rows, table has {}", name, array.len(), self.num_rows())))
+        }
+        let idx = self.columns().iter().position(|&r| r == name);
+        let mut fields = self.fields.clone();
+        match idx {
+            // a replaced column keeps its metadata, and stays non-nullable unless the new array has nulls
+            Some(idx) => {
+                let old = &self.fields[idx];
+                fields[idx] = Field::new(name, array.data_type().clone(), old.is_nullable || array.null_count() > 0)
+                    .with_metadata(old.metadata.clone());
+            },
+            None => fields.push(Field::new(name, array.data_type().clone(), array.null_count() > 0)),
+        }
+        let mut offset = 0;
+        let chunks = self.chunks
             .iter()
             .map(|chunk| {
-                (*chunk.columns().get(idx).unwrap()).as_ref()
+                let mut arrays = chunk.columns().to_vec();
+                let slice = array.slice(offset, chunk.len());
+                offset += chunk.len();
+                match idx {
+                    Some(idx) => arrays[idx] = slice,
+                    None => arrays.push(slice),
+                }
+                Chunk::new(arrays)
             })
+            .collect::<Vec<Chunk<Box<dyn Array>>>>();
+        let table = Self { fields, chunks };
+        table.validate()?;
+        Ok(table)
+    }
+
+    pub fn column(&self, column: &str) -> Result<Box<dyn Array>> {
+        let idx = self.position(column)?;
+        let arrays = self.chunks
+            .iter()
+            .map(|chunk| chunk.columns()[idx].as_ref())
             .collect::<Vec<&dyn Array>>();
-        concatenate(&arrays[..]).unwrap()
+        Ok(concatenate(&arrays[..])?)
     }
 
-    pub fn take(&self, idxs: Vec<u32>) -> Self {
+    pub fn take(&self, idxs: Vec<u32>) -> Result<Self> {
         let idx = PrimitiveArray::from(idxs.iter().map(|x| Some(*x)).collect::<Vec<Option<u32>>>());
+        self.take_array(&idx)
+    }
+
+    // Take rows where None results in a row of nulls
+    pub fn take_opt(&self, idxs: &[Option<u32>]) -> Result<Self> {
+        self.take_array(&PrimitiveArray::from(idxs))
+    }
+
+    fn take_array(&self, idx: &PrimitiveArray<u32>) -> Result<Self> {
         let arrays = (0..self.fields.len())
             .into_par_iter()
             .map(|i| {
                 if self.chunks.len() == 1 {
-                    take(self.chunks[0].columns()[i].as_ref(), &idx).unwrap()
+                    take(self.chunks[0].columns()[i].as_ref(), idx)
                 } else {
                     let arrs = self.chunks
                         .iter()
-                        .map(|chunk| (*chunk.columns().get(i).unwrap()).as_ref())
+                        .map(|chunk| chunk.columns()[i].as_ref())
                         .collect::<Vec<&dyn Array>>();
-                    take(concatenate(&arrs).unwrap().as_ref(), &idx).unwrap()
+                    take(concatenate(&arrs)?.as_ref(), idx)
                 }
             })
-            .collect::<Vec<Box<dyn Array>>>();
-        Self { fields: self.fields.clone(), chunks: vec![Chunk::new(arrays)] }
+            .collect::<arrow2::error::Result<Vec<Box<dyn Array>>>>()?;
+        Ok(Self { fields: self.fields.clone(), chunks: vec![Chunk::new(arrays)] })
     }
     
-    pub fn append(&mut self, other: &mut Table) {
-        self.table_eq(other);
+    pub fn append(&mut self, other: &mut Table) -> Result<()> {
+        self.table_eq(other)?;
         self.chunks.append(&mut other.chunks);
+        Ok(())
     }
 
-    pub fn upsert(&self, other: &Table, columns: &Vec<String>) -> Self {
-        self.table_eq(other);
+    // Key columns to match rows on: at least one, and all in the table
+    fn check_keys(&self, columns: &[String]) -> Result<()> {
+        if columns.is_empty() {
+            return Err(Error::InvalidArgument("Matching rows needs at least one key column".to_string()))
+        }
+        self.positions(columns)?;
+        Ok(())
+    }
+
+    pub fn upsert(&self, other: &Table, columns: &[String]) -> Result<Self> {
+        self.check_keys(columns)?;
+        self.table_eq(other)?;
 
         // Gather arrays of both tables
-        let left = columns.iter().map(|col| self.column(col)).collect::<Vec<Box<dyn Array>>>();
-        let right = columns.iter().map(|col| other.column(col)).collect::<Vec<Box<dyn Array>>>();
+        let left = columns.iter().map(|col| self.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
+        let right = columns.iter().map(|col| other.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
 
         // Merge to idxs
-        let (left_idxs, right_idxs) = merge_arrays(&left, &right);
+        let (left_idxs, right_idxs) = merge_arrays(&left, &right)?;
 
         // Index left & right + concatenate tables
-        let mut lt = self.take(left_idxs);
-        let mut rt = other.take(right_idxs);
-        lt.append(&mut rt);
-        lt
+        let mut lt = self.take(left_idxs)?;
+        let mut rt = other.take(right_idxs)?;
+        lt.append(&mut rt)?;
+        Ok(lt)
     }
 
-    pub fn delete(&self, other: &Table, columns: &Vec<String>) -> Self {
-        self.table_eq(other);
-        let left = columns.iter().map(|col| self.column(col)).collect::<Vec<Box<dyn Array>>>();
-        let right = columns.iter().map(|col| other.column(col)).collect::<Vec<Box<dyn Array>>>();
+    pub fn delete(&self, other: &Table, columns: &[String]) -> Result<Self> {
+        self.check_keys(columns)?;
+        self.table_eq(other)?;
+        let left = columns.iter().map(|col| self.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
+        let right = columns.iter().map(|col| other.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
 
         // Merge to idxs
-        let left_idxs = delete_arrays(&left, &right);
+        let left_idxs = delete_arrays(&left, &right)?;
 
         // Index left idxs
         self.take(left_idxs)
     }
 
-    pub fn filter(&self) {}
-
-    // pub fn groupby_test(&self, columns: &Vec<String>) {
-    //     let maps = self.chunks
-    //         .par_iter()
-    //         .map(|chunk| {
-    //             let arrays = columns
-    //                 .iter()
-    //                 .map(|column| {
-    //                     let idx = self.position(&column);
-    //                     chunk.columns().get(idx).unwrap().as_ref()                        
-    //                 })
-    //                 .collect::<Vec<&dyn Array>>();
-                
-    //             let map = groupby_many_test(arrays);
-    //         })
-    //         .collect::<Vec<()>>();
-    // }
-
-    pub fn groupby(&self, columns: &Vec<String>) -> Vec<DatasetPart> {
-        // 1. For loop over row_groups / chunks
-        // 2. Create hash grouping within row_groups
-        // 3. Groupby for each row_group
-        // 4. Gather results per key
-
-        let maps = self.chunks
-            .par_iter()
-            .map(|chunk| {
-                let arrays = columns
-                    .iter()
-                    .map(|column| {
-                        let idx = self.position(&column);
-                        chunk.columns().get(idx).unwrap().as_ref()                        
-                    })
-                    .collect::<Vec<&dyn Array>>();
-                
-                let map = groupby_many(arrays);
-                map
-                    .into_par_iter()
-                    .map(|(k, v)| {
-                        (k, chunk_take(&chunk, &v))
-                    })
-                    .collect::<HashMap<Vec<String>, Chunk<Box<dyn Array>>>>()
+    pub fn join(&self, other: &Table, left_on: &[String], right_on: &[String], how: JoinType) -> Result<Self> {
+        self.join_with_suffix(other, left_on, right_on, how, "_right")
+    }
 
-            })
-            .collect::<Vec<HashMap<Vec<String>, Chunk<Box<dyn Array>>>>>();
+    // Hash join on (composite) keys; right columns whose name clashes with a left column get the suffix
+    pub fn join_with_suffix(&self, other: &Table, left_on: &[String], right_on: &[String], how: JoinType, suffix: &str) -> Result<Self> {
+        if left_on.len() != right_on.len() {
+            return Err(Error::InvalidArgument("Join needs the same number of keys on both sides".to_string()))
+        }
+        if left_on.is_empty() {
+            return Err(Error::InvalidArgument("Join needs at least one key column".to_string()))
+        }
+        let left = left_on.iter().map(|col| self.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
+        let right = right_on.iter().map(|col| other.column(col)).collect::<Result<Vec<Box<dyn Array>>>>()?;
+
+        // Right columns clashing with a left column get the suffix, which must not clash again
+        let right_names = other.fields
+            .iter()
+            .map(|f| if self.fields.iter().any(|lf| lf.name == f.name) {format!("{}{}", f.name, suffix)} else {f.name.clone()})
+            .collect::<Vec<String>>();
+        if how != JoinType::Semi && how != JoinType::Anti {
+            let mut names = HashSet::new();
+            if let Some(name) = self.fields.iter().map(|f| &f.name).chain(&right_names).find(|name| !names.insert(*name)) {
+                return Err(Error::InvalidArgument(format!("Join produces duplicate column {}", name)))
+            }
+        }
+
+        // Join to idxs
+        let (left_idxs, right_idxs) = join_arrays(&left, &right, how)?;
+
+        // Index left (& right) and place columns side by side
+        let lt = self.take_opt(&left_idxs)?;
+        if how == JoinType::Semi || how == JoinType::Anti {
+            return Ok(lt)
+        }
+        let rt = other.take_opt(&right_idxs)?;
+
+        let mut fields = lt.fields
+            .iter()
+            .map(|f| Field::new(&f.name, f.data_type.clone(), f.is_nullable || how == JoinType::Right || how == JoinType::Full))
+            .collect::<Vec<Field>>();
+        for (f, name) in rt.fields.iter().zip(&right_names) {
+            fields.push(Field::new(name, f.data_type.clone(), f.is_nullable || how == JoinType::Left || how == JoinType::Full));
+        }
+        let mut arrays = lt.chunks[0].clone().into_arrays();
+        arrays.extend(rt.chunks[0].clone().into_arrays());
+        Ok(Self { fields, chunks: vec![Chunk::new(arrays)] })
+    }
 
-        let tables = hashmaps_merge(maps)
+    pub fn filter(&self, expr: &FilterExpr) -> Result<Self> {
+        let chunks = self.chunks
             .par_iter()
-            .map(|(k, v)| {
-                let filters = columns.clone().into_iter().zip(k.into_iter().map(|kr| kr.clone())).collect::<HashMap<String, String>>();
-                let table = Table { fields:self.fields.clone(), chunks: v.clone() };
-                DatasetPart::new(Some(table), Some(filters), None)
-            })
-            .collect::<Vec<DatasetPart>>();
-        tables
+            .map(|chunk| filter_chunk_expr(&self.fields, chunk, expr))
+            .collect::<Result<Vec<Chunk<Box<dyn Array>>>>>()?
+            .into_iter()
+            .filter(|chunk| !chunk.is_empty())
+            .collect::<Vec<Chunk<Box<dyn Array>>>>();
+        if chunks.is_empty() {
+            return Ok(Table::empty(self.fields.clone()))
+        }
+        Ok(Self { fields: self.fields.clone(), chunks })
     }
 
-    pub fn to_dataset(&self, partitions: Option<Vec<String>>, buckets: Option<Vec<String>>, storage: Option<DatasetStorage>) -> Dataset {
-        let parts = match &partitions {
-            Some(partitions) => self.groupby(&partitions),
-            None => {
-                vec![DatasetPart::new(Some(self.clone()), Some(HashMap::<String, String>::new()), None)]
-            }
-        };
-        Dataset::new(partitions.clone(), buckets.clone(), parts, storage)
+    // Sort on columns (lexicographic), descending per column; nulls first or last in every column
+    pub fn sort_by(&self, columns: &[String], descending: &[bool], nulls_first: bool) -> Result<Self> {
+        if columns.len() != descending.len() {
+            return Err(Error::InvalidArgument("Columns and descending should have equal length".to_string()))
+        }
+        let idxs = self.positions(columns)?;
+        let (chunks, order) = sort_chunks(&self.chunks, &idxs, descending, nulls_first)?;
+        let sorted = Self { fields: self.fields.clone(), chunks };
+        if sorted.chunks.len() > 1 {sorted.take(order)} else {Ok(sorted)}
+    }
+
+    pub fn groupby(&self, columns: &[String]) -> Result<GroupBy<'_>> {
+        GroupBy::new(self, columns)
+    }
+
+    // Dataset with one part per partition, or per bucket of each partition when bucketed
+    pub fn to_dataset(&self, partitions: Option<Vec<String>>, buckets: Option<BucketSpec>, storage: Option<DatasetStorage>) -> Result<Dataset> {
+        let mut dataset = Dataset::new(partitions, buckets, vec![], storage, Some(self.fields.clone()));
+        dataset.parts = dataset.split(self)?;
+        Ok(dataset)
     }
 
     // IO RELATED
-    pub fn to_parquet(&self, path: &String) {
-        write_parquet(path, self.fields.clone().into(), &self.chunks).expect("Writing Table to parquet failed");
+    pub fn to_parquet(&self, path: &str) -> Result<()> {
+        self.to_parquet_with(path, &WriteOptions::default())
+    }
+
+    pub fn to_parquet_with(&self, path: &str, options: &WriteOptions) -> Result<()> {
+        write_parquet(path, self.fields.clone().into(), &self.chunks, options)
+    }
+
+    pub fn from_ipc(path: &str) -> Result<Self> {
+        read_ipc(path)
+    }
+
+    pub fn to_ipc(&self, path: &str) -> Result<()> {
+        self.to_ipc_with(path, &None)
+    }
+
+    // IPC compression: Lz4Raw (LZ4 frame) or Zstd
+    pub fn to_ipc_with(&self, path: &str, compression: &Option<Compression>) -> Result<()> {
+        write_ipc(path, self.fields.clone().into(), &self.chunks, compression)
+    }
+
+    pub fn from_csv(path: &str, options: &CsvOptions) -> Result<Self> {
+        read_csv(path, options)
+    }
+
+    pub fn to_csv(&self, path: &str) -> Result<()> {
+        self.to_csv_with(path, &CsvOptions::default())
+    }
+
+    pub fn to_csv_with(&self, path: &str, options: &CsvOptions) -> Result<()> {
+        write_csv(path, &self.fields, &self.chunks, options)
+    }
+
+    pub fn from_ndjson(path: &str, options: &JsonOptions) -> Result<Self> {
+        read_ndjson(path, options)
+    }
+
+    pub fn to_ndjson(&self, path: &str) -> Result<()> {
+        write_ndjson(path, &self.fields, &self.chunks)
     }
 
 }
@@ -202,14 +417,293 @@ impl Table {
 
 #[cfg(test)]
 mod tests {
+    use arrow2::{
+        array::{Int32Array, Int64Array, Float64Array, BinaryArray, Utf8Array},
+        chunk::Chunk,
+        compute::concatenate::concatenate,
+        datatypes::{DataType, Field, TimeUnit},
+        scalar::PrimitiveScalar,
+    };
+
+    use std::collections::{BTreeMap, HashMap};
+
     use crate::io::factory::create_random_table;
+    use crate::io::parquet::read::{read_parquet, read_parquet_where};
+    use crate::io::parquet::write::{WriteOptions, ColumnEncoding, RowGroupSize};
+    use crate::io::ipc::read::read_ipc_with;
+    use crate::io::csv::CsvOptions;
+    use crate::io::csv::read::read_csv_with;
+    use crate::io::json::JsonOptions;
+    use crate::io::json::read::read_ndjson_with;
+    use crate::io::ReadOptions;
+    use crate::core::dataset::Compression;
+    use crate::core::table::Table;
+    use crate::core::filter::{FilterExpr, FilterOp};
+    use crate::core::agg::Agg;
+    use crate::core::join::JoinType;
+    use crate::error::Error;
 
     #[test]
     fn test_append() {
         let mut t1 = create_random_table(2);
         let mut t2 = create_random_table(1);
-        let len = &t1.num_rows() + &t2.num_rows();
-        t1.append(&mut t2);
+        let len = t1.num_rows() + t2.num_rows();
+        t1.append(&mut t2).unwrap();
         assert_eq!(&t1.num_rows(), &len);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_filter() {
+        let t = create_random_table(2);
+        let expr = FilterExpr::scalar("c1", FilterOp::Equal, PrimitiveScalar::new(DataType::Int32, Some(3)))
+            .and(FilterExpr::scalar("c4", FilterOp::Less, PrimitiveScalar::new(DataType::Int64, Some(150_000i64))));
+        let filtered = t.filter(&expr).unwrap();
+        assert_eq!(filtered.fields, t.fields);
+        assert_eq!(filtered.num_rows(), 15_000);
+
+        // No matching rows leaves an empty table that can be used further
+        let none = t.filter(&FilterExpr::scalar("c1", FilterOp::Equal, PrimitiveScalar::new(DataType::Int32, Some(10)))).unwrap();
+        assert_eq!(none.num_rows(), 0);
+        assert_eq!(none.sort_by(&["c4".to_string()], &[false], false).unwrap().num_rows(), 0);
+        assert_eq!(none.column("c3").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_read_parquet_where() {
+        let expr = FilterExpr::scalar("c1", FilterOp::Equal, PrimitiveScalar::new(DataType::Int32, Some(3)))
+            .and(FilterExpr::scalar("c4", FilterOp::Less, PrimitiveScalar::new(DataType::Int64, Some(150_000i64))));
+
+        // Row groups (one per chunk) past c4 < 150_000 are skipped on their statistics
+        let path = std::env::temp_dir().join("arrow_lake_test_read_parquet_where.parquet").to_str().unwrap().to_string();
+        create_random_table(3).to_parquet(&path).unwrap();
+        let read = read_parquet_where(&path, &expr).unwrap();
+        assert_eq!(read.num_rows(), 15_000);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_upsert_delete() {
+        let t = create_random_table(2);
+        let head = t.head(&1_000);
+        let keys = vec!["c3".to_string(), "c1".to_string()];
+        assert_eq!(t.upsert(&head, &keys).unwrap().num_rows(), t.num_rows());
+        assert_eq!(t.delete(&head, &keys).unwrap().num_rows(), t.num_rows() - 1_000);
+        assert!(matches!(t.upsert(&head, &[]), Err(Error::InvalidArgument(_))));
+        assert!(matches!(t.delete(&head, &[]), Err(Error::InvalidArgument(_))));
+        assert!(matches!(t.upsert(&head, &["c9".to_string()]), Err(Error::ColumnNotFound(_))));
+    }
+
+    #[test]
+    fn test_groupby_agg() {
+        let t = create_random_table(2);
+        let keys = vec!["c1".to_string()];
+        let aggs = t.groupby(&keys).unwrap().agg(&[Agg::count_rows(), Agg::sum("c4"), Agg::min("c3"), Agg::mean("c2").alias("avg")]).unwrap();
+        assert_eq!(aggs.columns(), vec!["c1", "count", "c4_sum", "c3_min", "avg"]);
+        assert_eq!(aggs.num_rows(), 10);
+        let counts = aggs.column("count").unwrap();
+        assert_eq!(counts.as_any().downcast_ref::<Int64Array>().unwrap(), &Int64Array::from_vec(vec![20_000; 10]));
+        let sums = aggs.column("c4_sum").unwrap();
+        let total = sums.as_any().downcast_ref::<Int64Array>().unwrap().values_iter().sum::<i64>();
+        assert_eq!(total, (0..200_000i64).sum::<i64>());
+        assert!(matches!(t.groupby(&["c9".to_string()]), Err(Error::ColumnNotFound(_))));
+        assert!(matches!(t.groupby(&[]), Err(Error::InvalidArgument(_))));
+
+        // float, binary & duration keys
+        let fields = vec![
+            Field::new("f", DataType::Float64, true),
+            Field::new("b", DataType::Binary, true),
+            Field::new("d", DataType::Duration(TimeUnit::Second), true),
+        ];
+        let f = Float64Array::from([Some(1.5), Some(2.5), Some(1.5), None, Some(f64::NAN), Some(f64::NAN)]);
+        let b = BinaryArray::<i32>::from([Some(b"a".as_ref()), Some(b"b"), Some(b"a"), None, Some(b"b"), Some(b"c")]);
+        let d = Int64Array::from_slice([1, 1, 2, 2, 3, 3]).to(DataType::Duration(TimeUnit::Second));
+        let t = Table::new(fields, vec![Chunk::new(vec![f.boxed(), b.boxed(), d.boxed()])]);
+        let count = |keys: &[&str]| {
+            let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>();
+            t.groupby(&keys).unwrap().agg(&[Agg::count_rows()]).unwrap().num_rows()
+        };
+        assert_eq!(count(&["f"]), 4);
+        assert_eq!(count(&["b"]), 4);
+        assert_eq!(count(&["d"]), 3);
+        assert_eq!(count(&["f", "b"]), 5);
+    }
+
+    #[test]
+    fn test_join() {
+        let t1 = create_random_table(1);
+        let t2 = create_random_table(1).head(&5_000);
+        let keys = vec!["c2".to_string(), "c3".to_string()];
+        let joined = t1.join(&t2, &keys, &keys, JoinType::Left).unwrap();
+        assert_eq!(joined.num_rows(), t1.num_rows());
+        assert_eq!(joined.columns()[4..], ["c1_right", "c2_right", "c3_right", "c4_right"]);
+        assert_eq!(joined.column("c4_right").unwrap().null_count(), t1.num_rows() - 5_000);
+        assert_eq!(t1.join(&t2, &keys, &keys, JoinType::Inner).unwrap().num_rows(), 5_000);
+        assert_eq!(t1.join(&t2, &keys, &keys, JoinType::Anti).unwrap().num_rows(), t1.num_rows() - 5_000);
+
+        let clash = t1.with_column("c4_right", t1.column("c4").unwrap()).unwrap();
+        assert!(clash.join(&t2, &keys, &keys, JoinType::Left).is_err());
+        assert!(matches!(t1.join(&t2, &[], &[], JoinType::Inner), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let t = create_random_table(3);
+        let sorted = t.sort_by(&["c1".to_string(), "c4".to_string()], &[false, true], false).unwrap();
+        assert_eq!(sorted.num_rows(), t.num_rows());
+        let c1 = sorted.column("c1").unwrap();
+        let c1 = c1.as_any().downcast_ref::<Int32Array>().unwrap();
+        let c4 = sorted.column("c4").unwrap();
+        let c4 = c4.as_any().downcast_ref::<Int64Array>().unwrap();
+        let rows = c1.values_iter().zip(c4.values_iter()).collect::<Vec<(&i32, &i64)>>();
+        assert!(rows.windows(2).all(|w| w[0].0 < w[1].0 || (w[0].0 == w[1].0 && w[0].1 >= w[1].1)));
+    }
+
+    #[test]
+    fn test_column_ops() {
+        let t = create_random_table(2);
+        let names = |t: &Table| t.columns().into_iter().cloned().collect::<Vec<String>>();
+        let cols = |v: &[&str]| v.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+        assert_eq!(names(&t.select(&cols(&["c4", "c1"])).unwrap()), cols(&["c4", "c1"]));
+        assert_eq!(names(&t.drop(&cols(&["c2", "c3"])).unwrap()), cols(&["c1", "c4"]));
+        assert_eq!(names(&t.reorder(&cols(&["c4", "c3", "c2", "c1"])).unwrap()), cols(&["c4", "c3", "c2", "c1"]));
+        let renamed = t.rename(&HashMap::from([("c1".to_string(), "key".to_string())])).unwrap();
+        assert_eq!(names(&renamed), cols(&["key", "c2", "c3", "c4"]));
+
+        let added = t.with_column("c5", t.column("c2").unwrap()).unwrap();
+        assert_eq!(added.chunks.len(), t.chunks.len());
+        assert_eq!(added.column("c5").unwrap(), t.column("c2").unwrap());
+        assert_eq!(added.fields[4].is_nullable, t.column("c2").unwrap().null_count() > 0);
+        let mut fields = t.fields.clone();
+        fields[0] = fields[0].clone().with_metadata(BTreeMap::from([("unit".to_string(), "kg".to_string())]));
+        let replaced = Table::new(fields.clone(), t.chunks.clone()).with_column("c1", t.column("c4").unwrap()).unwrap();
+        assert_eq!(replaced.fields[0].data_type(), &DataType::Int64);
+        assert_eq!(replaced.fields[0].metadata, fields[0].metadata);
+        assert!(replaced.fields[0].is_nullable);
+
+        assert!(matches!(t.select(&cols(&["c9"])), Err(Error::ColumnNotFound(_))));
+        assert!(matches!(t.rename(&HashMap::from([("c1".to_string(), "c2".to_string())])), Err(Error::SchemaMismatch(_))));
+        assert!(matches!(t.with_column("c5", t.head(&10).column("c2").unwrap()), Err(Error::SchemaMismatch(_))));
+    }
+
+    #[test]
+    fn test_parquet_write_options() {
+        let path = std::env::temp_dir().join("arrow_lake_test_write_options.parquet").to_str().unwrap().to_string();
+        let t = create_random_table(2);
+        let options = WriteOptions {
+            compression: Compression::Zstd(Some(3)),
+            encodings: HashMap::from([
+                ("c1".to_string(), ColumnEncoding::Dictionary),
+                ("c3".to_string(), ColumnEncoding::DeltaLengthByteArray),
+                ("c4".to_string(), ColumnEncoding::DeltaBinaryPacked),
+            ]),
+            row_group_size: Some(RowGroupSize::Rows(30_000)),
+            page_size: Some(64 * 1024),
+            statistics: true,
+        };
+        t.to_parquet_with(&path, &options).unwrap();
+
+        let metadata = arrow2::io::parquet::read::read_metadata(&mut std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(metadata.row_groups.len(), 7);
+        let column = &metadata.row_groups[0].columns()[0];
+        assert_eq!(format!("{:?}", column.compression()), "Zstd");
+        let read = read_parquet(&path).unwrap();
+        assert_eq!(read.fields, t.fields);
+        let concat = |t: &Table, i: usize| concatenate(&t.chunks.iter().map(|c| c.arrays()[i].as_ref()).collect::<Vec<_>>()).unwrap();
+        assert!((0..4).all(|i| concat(&read, i) == concat(&t, i)));
+
+        let invalid = WriteOptions { encodings: HashMap::from([("c3".to_string(), ColumnEncoding::DeltaBinaryPacked)]), ..Default::default() };
+        assert!(matches!(t.to_parquet_with(&path, &invalid), Err(Error::InvalidArgument(_))));
+        let invalid = WriteOptions { compression: Compression::Gzip(Some(42)), ..Default::default() };
+        assert!(matches!(t.to_parquet_with(&path, &invalid), Err(Error::InvalidArgument(_))));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_ipc() {
+        let path = std::env::temp_dir().join("arrow_lake_test_ipc.arrow").to_str().unwrap().to_string();
+        let t = create_random_table(2);
+        t.to_ipc_with(&path, &Some(Compression::Zstd(None))).unwrap();
+        let read = Table::from_ipc(&path).unwrap();
+        assert_eq!((&read.fields, read.chunks.len()), (&t.fields, 2));
+        assert!(read.chunks.iter().zip(&t.chunks).all(|(l, r)| l == r));
+
+        let options = ReadOptions { columns: Some(vec!["c3".to_string()]), row_limit: Some(10), ..Default::default() };
+        let read = read_ipc_with(&path, &options).unwrap();
+        assert_eq!((read.columns(), read.num_rows()), (vec![&"c3".to_string()], 10));
+        assert!(matches!(t.to_ipc_with(&path, &Some(Compression::Snappy)), Err(Error::InvalidArgument(_))));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_csv() {
+        let path = std::env::temp_dir().join("arrow_lake_test_csv.csv").to_str().unwrap().to_string();
+        std::fs::write(&path, "id;score;name;flag\n1;1.5;a;true\n2;NA;\"b;c\";false\n3;2;NA;\n").unwrap();
+        let options = CsvOptions { delimiter: b';', null_values: vec!["NA".to_string(), "".to_string()], ..Default::default() };
+        let read = Table::from_csv(&path, &options).unwrap();
+        let types = read.fields.iter().map(|f| f.data_type.clone()).collect::<Vec<DataType>>();
+        assert_eq!(types, vec![DataType::Int64, DataType::Float64, DataType::Utf8, DataType::Boolean]);
+        let nulls = read.chunks[0].columns().iter().map(|c| c.null_count()).collect::<Vec<usize>>();
+        assert_eq!(nulls, vec![0, 1, 1, 1]);
+
+        // explicit schema, parsed in several batches
+        let t = create_random_table(1);
+        t.to_csv(&path).unwrap();
+        let options = CsvOptions { schema: Some(t.fields.clone()), ..Default::default() };
+        let read = read_csv_with(&path, &options, &ReadOptions { batch_size: Some(10_000), ..Default::default() }).unwrap();
+        assert_eq!((&read.fields, read.chunks.len()), (&t.fields, 10));
+        assert_eq!(concatenate(&read.chunks.iter().map(|c| c.columns()[2].as_ref()).collect::<Vec<_>>()).unwrap(), t.chunks[0].columns()[2]);
+
+        let predicate = FilterExpr::scalar("c2", FilterOp::Less, PrimitiveScalar::new(DataType::Int32, Some(100)));
+        let read_options = ReadOptions { columns: Some(vec!["c3".to_string()]), predicate: Some(predicate), ..Default::default() };
+        let read = read_csv_with(&path, &options, &read_options).unwrap();
+        assert_eq!((read.columns(), read.num_rows()), (vec![&"c3".to_string()], 100));
+
+        // empty strings and nulls round-trip with the default options
+        let name = Utf8Array::<i32>::from([Some(""), None, Some("a")]);
+        let id = Int64Array::from([Some(1), None, Some(3)]);
+        let t = Table::new(
+            vec![Field::new("name", DataType::Utf8, true), Field::new("id", DataType::Int64, true)],
+            vec![Chunk::new(vec![name.clone().boxed(), id.clone().boxed()])],
+        );
+        t.to_csv(&path).unwrap();
+        let read = Table::from_csv(&path, &CsvOptions::default()).unwrap();
+        assert_eq!(read.fields, t.fields);
+        assert_eq!(read.column("name").unwrap(), name.boxed());
+        assert_eq!(read.column("id").unwrap(), id.boxed());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let path = std::env::temp_dir().join("arrow_lake_test_ndjson.json").to_str().unwrap().to_string();
+        let events = [
+            r#"{"id": 1, "user": {"name": "a", "age": 30}, "tags": ["x", "y"]}"#,
+            r#"{"id": 2, "user": {"name": "b"}, "tags": []}"#,
+            "",
+            r#"{"id": 3, "score": 0.5}"#,
+        ];
+        std::fs::write(&path, events.join("\n")).unwrap();
+        let read = Table::from_ndjson(&path, &JsonOptions::default()).unwrap();
+        assert_eq!(read.columns(), vec!["id", "user", "tags", "score"]);
+        let user = DataType::Struct(vec![Field::new("name", DataType::Utf8, true), Field::new("age", DataType::Int64, true)]);
+        let tags = DataType::List(Box::new(Field::new("item", DataType::Utf8, true)));
+        let types = read.fields.iter().map(|f| f.data_type.clone()).collect::<Vec<DataType>>();
+        assert_eq!(types, vec![DataType::Int64, user, tags, DataType::Float64]);
+        let nulls = read.chunks[0].columns().iter().map(|c| c.null_count()).collect::<Vec<usize>>();
+        assert_eq!((read.num_rows(), nulls), (3, vec![0, 1, 1, 2]));
+
+        // nested columns roundtrip
+        read.to_ndjson(&path).unwrap();
+        let options = JsonOptions { schema: Some(read.fields.clone()), ..Default::default() };
+        let roundtrip = Table::from_ndjson(&path, &options).unwrap();
+        assert_eq!(roundtrip.chunks, read.chunks);
+
+        let t = create_random_table(1);
+        t.to_ndjson(&path).unwrap();
+        let options = JsonOptions { schema: Some(t.fields.clone()), ..Default::default() };
+        let read_options = ReadOptions { columns: Some(vec!["c4".to_string()]), row_limit: Some(10), ..Default::default() };
+        let read = read_ndjson_with(&path, &options, &read_options).unwrap();
+        assert_eq!((read.columns(), read.num_rows()), (vec![&"c4".to_string()], 10));
+        std::fs::remove_file(&path).ok();
+    }
+}
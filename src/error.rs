@@ -0,0 +1,68 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    ColumnNotFound(String),
+    SchemaMismatch(String),
+    UnsupportedType(String),
+    InvalidArgument(String),
+    Io(std::io::Error),
+    Manifest(String),
+    Parquet(arrow2::error::Error),
+    Ipc(arrow2::error::Error),
+    Csv(arrow2::error::Error),
+    Json(arrow2::error::Error),
+    Arrow(arrow2::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ColumnNotFound(column) => write!(f, "Column {} not found", column),
+            Error::SchemaMismatch(msg) => write!(f, "Schema mismatch: {}", msg),
+            Error::UnsupportedType(msg) => write!(f, "Unsupported type: {}", msg),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Manifest(msg) => write!(f, "Manifest error: {}", msg),
+            Error::Parquet(e) => write!(f, "Parquet error: {}", e),
+            Error::Ipc(e) => write!(f, "IPC error: {}", e),
+            Error::Csv(e) => write!(f, "CSV error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::Arrow(e) => write!(f, "Arrow error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parquet(e) | Error::Ipc(e) | Error::Csv(e) | Error::Json(e) | Error::Arrow(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Manifest(e.to_string())
+    }
+}
+
+// Parquet, IPC, CSV & JSON IO map their errors to their own variant explicitly, everything else is a compute error
+impl From<arrow2::error::Error> for Error {
+    fn from(e: arrow2::error::Error) -> Self {
+        match e {
+            arrow2::error::Error::Io(e) => Error::Io(e),
+            e => Error::Arrow(e),
+        }
+    }
+}
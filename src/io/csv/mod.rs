@@ -0,0 +1,47 @@
+use arrow2::datatypes::Field;
+
+use crate::error::Error;
+
+pub mod read;
+pub mod write;
+
+// Default null token, so that empty strings and nulls of text columns are told apart
+pub const NULL_MARKER: &str = "\\N";
+
+// Options for reading & writing CSV files: delimiter, quote character, whether the first line is a header,
+// tokens read as null (text nulls are written as the first one), number of rows the schema is inferred from
+// (all when None) or an explicit schema. Empty fields are null in non-text columns whatever the null tokens.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub has_header: bool,
+    pub null_values: Vec<String>,
+    pub infer_rows: Option<usize>,
+    pub schema: Option<Vec<Field>>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            has_header: true,
+            null_values: vec![NULL_MARKER.to_string()],
+            infer_rows: Some(1000),
+            schema: None,
+        }
+    }
+}
+
+pub(crate) fn csv_error(e: arrow2::error::Error) -> Error {
+    match e {
+        arrow2::error::Error::Io(e) => Error::Io(e),
+        e => Error::Csv(e),
+    }
+}
+
+// Errors of the underlying csv reader
+pub(crate) fn external_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::Csv(arrow2::error::Error::External("".to_string(), Box::new(e)))
+}
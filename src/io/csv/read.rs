@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use rayon::prelude::*;
+
+use arrow2::{
+    array::Array,
+    bitmap::Bitmap,
+    chunk::Chunk,
+    datatypes::{DataType, Field},
+    io::csv::read::{self, ByteRecord, Reader, ReaderBuilder},
+};
+
+use crate::core::table::Table;
+use crate::io::ReadOptions;
+use crate::io::csv::{CsvOptions, csv_error, external_error};
+use crate::error::{Error, Result};
+
+// Default number of rows per chunk
+const BATCH_SIZE: usize = 1024 * 8 * 8;
+
+pub fn read_csv(path: &str, options: &CsvOptions) -> Result<Table> {
+    read_csv_with(path, options, &ReadOptions::default())
+}
+
+// Rows are read sequentially, one batch per thread at a time, and the batches are parsed in parallel
+pub fn read_csv_with(path: &str, csv: &CsvOptions, options: &ReadOptions) -> Result<Table> {
+    let mut reader = open(path, csv)?;
+    let fields = match &csv.schema {
+        Some(schema) => {
+            let width = reader.byte_headers().map_err(external_error)?.len();
+            if width > 0 && width != schema.len() {
+                return Err(Error::SchemaMismatch(format!("{} has {} columns, schema has {}", path, width, schema.len())))
+            }
+            schema.clone()
+        },
+        None => infer_fields(path, csv)?,
+    };
+
+    // only the projected columns and the columns the predicate needs are parsed
+    let projection = options.projection(&fields)?.unwrap_or_else(|| (0..fields.len()).collect());
+    // without a predicate the limit is known upfront
+    let mut remaining = match &options.predicate {
+        Some(_) => usize::MAX,
+        None => options.row_limit.unwrap_or(usize::MAX),
+    };
+    let batch_size = options.batch_size.unwrap_or(BATCH_SIZE);
+
+    let mut chunks = Vec::new();
+    let mut line = csv.has_header as usize;
+    let mut done = false;
+    while !done && remaining > 0 {
+        let mut batches = Vec::new();
+        for _ in 0..rayon::current_num_threads() {
+            let n = batch_size.min(remaining);
+            let mut rows = vec![ByteRecord::new(); n];
+            let read = read::read_rows(&mut reader, 0, &mut rows).map_err(csv_error)?;
+            rows.truncate(read);
+            if read > 0 {
+                batches.push((line, rows));
+            }
+            line += read;
+            remaining -= read;
+            if read < n || remaining == 0 {
+                done = true;
+                break
+            }
+        }
+        let parsed = batches
+            .into_par_iter()
+            .map(|(line, rows)| deserialize(&rows, &fields, &projection, line, &csv.null_values))
+            .collect::<Result<Vec<_>>>()?;
+        chunks.extend(parsed);
+    }
+
+    let fields = projection.iter().map(|i| fields[*i].clone()).collect();
+    let table = options.apply(Table::new(fields, chunks))?;
+    Ok(match options.row_limit {
+        Some(row_limit) => table.head(&row_limit),
+        None => table,
+    })
+}
+
+fn open(path: &str, csv: &CsvOptions) -> Result<Reader<BufReader<File>>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(ReaderBuilder::new()
+        .delimiter(csv.delimiter)
+        .quote(csv.quote)
+        .has_headers(csv.has_header)
+        .from_reader(file))
+}
+
+fn is_null(value: &[u8], null_values: &[String]) -> bool {
+    null_values.iter().any(|v| v.as_bytes() == value)
+}
+
+// Fields from the header (or column_1, column_2, ... without one), typed on the first infer_rows rows.
+// Null tokens and empty fields don't count, columns with incompatible types (or only nulls) are read as Utf8.
+fn infer_fields(path: &str, csv: &CsvOptions) -> Result<Vec<Field>> {
+    let mut reader = open(path, csv)?;
+    let headers = reader.byte_headers().map_err(external_error)?;
+    let names = match csv.has_header {
+        true => headers.iter().map(|h| String::from_utf8_lossy(h).to_string()).collect::<Vec<String>>(),
+        false => (0..headers.len()).map(|i| format!("column_{}", i + 1)).collect(),
+    };
+
+    let mut types = vec![HashSet::new(); names.len()];
+    let mut record = ByteRecord::new();
+    let mut n = 0;
+    while n < csv.infer_rows.unwrap_or(usize::MAX) && reader.read_byte_record(&mut record).map_err(external_error)? {
+        for (column, value) in types.iter_mut().zip(record.iter()) {
+            if !value.is_empty() && !is_null(value, &csv.null_values) {
+                column.insert(read::infer(value));
+            }
+        }
+        n += 1;
+    }
+
+    Ok(names
+        .into_iter()
+        .zip(types)
+        .map(|(name, types)| {
+            let data_type = match types.len() {
+                1 => types.into_iter().next().unwrap(),
+                2 if types.contains(&DataType::Int64) && types.contains(&DataType::Float64) => DataType::Float64,
+                _ => DataType::Utf8,
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect())
+}
+
+fn deserialize(rows: &[ByteRecord], fields: &[Field], projection: &[usize], line: usize, null_values: &[String]) -> Result<Chunk<Box<dyn Array>>> {
+    let arrays = projection
+        .iter()
+        .map(|i| {
+            let array = read::deserialize_column(rows, *i, fields[*i].data_type.clone(), line).map_err(csv_error)?;
+            Ok(mask_nulls(array, rows, *i, null_values))
+        })
+        .collect::<Result<Vec<Box<dyn Array>>>>()?;
+    Chunk::try_new(arrays).map_err(csv_error)
+}
+
+// Null tokens parse as values for text columns (and as nulls otherwise): unset them in the validity
+fn mask_nulls(array: Box<dyn Array>, rows: &[ByteRecord], column: usize, null_values: &[String]) -> Box<dyn Array> {
+    if null_values.is_empty() {
+        return array
+    }
+    let mask = rows
+        .iter()
+        .map(|row| row.get(column).is_some_and(|v| !is_null(v, null_values)))
+        .collect::<Bitmap>();
+    if mask.unset_bits() == 0 {
+        return array
+    }
+    let validity = match array.validity() {
+        Some(validity) => validity & &mask,
+        None => mask,
+    };
+    array.with_validity(Some(validity))
+}
@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use arrow2::{
+    array::{Array, Utf8Array},
+    chunk::Chunk,
+    datatypes::Field,
+    io::csv::write::{self, SerializeOptions},
+    types::Offset,
+};
+
+use crate::io::csv::{CsvOptions, csv_error};
+use crate::error::Result;
+
+// Nulls of text columns are written as the first null token of options (so they don't read back as empty
+// strings), other nulls as empty fields
+pub fn write_csv(path: &str, fields: &[Field], chunks: &[Chunk<Box<dyn Array>>], options: &CsvOptions) -> Result<()> {
+    write_chunks(path, fields, chunks, options).map_err(csv_error)
+}
+
+fn write_chunks(path: &str, fields: &[Field], chunks: &[Chunk<Box<dyn Array>>], options: &CsvOptions) -> arrow2::error::Result<()> {
+    if let Some(path_dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(path_dir)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    let serialize = SerializeOptions { delimiter: options.delimiter, quote: options.quote, ..Default::default() };
+    if options.has_header {
+        let names = fields.iter().map(|f| &f.name).collect::<Vec<&String>>();
+        write::write_header(&mut file, &names, &serialize)?;
+    }
+    for chunk in chunks {
+        match options.null_values.first() {
+            Some(marker) if !marker.is_empty() => {
+                let arrays = chunk.arrays().iter().map(|a| mark_nulls(a.as_ref(), marker)).collect();
+                write::write_chunk(&mut file, &Chunk::new(arrays), &serialize)?;
+            },
+            _ => write::write_chunk(&mut file, chunk, &serialize)?,
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+fn mark_nulls(array: &dyn Array, marker: &str) -> Box<dyn Array> {
+    if array.null_count() > 0 {
+        if let Some(array) = array.as_any().downcast_ref::<Utf8Array<i32>>() {
+            return utf8_mark_nulls(array, marker)
+        }
+        if let Some(array) = array.as_any().downcast_ref::<Utf8Array<i64>>() {
+            return utf8_mark_nulls(array, marker)
+        }
+    }
+    array.to_boxed()
+}
+
+fn utf8_mark_nulls<O: Offset>(array: &Utf8Array<O>, marker: &str) -> Box<dyn Array> {
+    Utf8Array::<O>::from_iter_values(array.iter().map(|v| v.unwrap_or(marker))).boxed()
+}
@@ -42,4 +42,24 @@ pub fn create_random_table(size: usize) -> Table {
     }
 
     Table::new(fields, chunks)
-}
\ No newline at end of file
+}
+
+// Table of (key: Utf8, id: Int32) rows, for tests on partitioned datasets
+pub fn create_key_table(keys: &[&str], ids: &[i32]) -> Table {
+    let fields = vec![
+        Field::new("key", DataType::Utf8, true),
+        Field::new("id", DataType::Int32, true),
+    ];
+    let chunk = Chunk::new(vec![
+        Utf8Array::<i32>::from_slice(keys).boxed(),
+        Int32Array::from_slice(ids).boxed(),
+    ]);
+    Table::new(fields, vec![chunk])
+}
+
+// Empty directory under the temp dir, for tests writing datasets
+pub fn create_temp_root(name: &str) -> String {
+    let root = std::env::temp_dir().join(format!("arrow_lake_test_{}", name)).to_string_lossy().to_string();
+    std::fs::remove_dir_all(&root).ok();
+    root
+}
@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use arrow2::io::ipc::read::{read_file_metadata, FileReader};
+
+use crate::core::table::Table;
+use crate::io::ReadOptions;
+use crate::error::{Error, Result};
+
+pub fn read_ipc(path: &str) -> Result<Table> {
+    read_ipc_with(path, &ReadOptions::default())
+}
+
+// Batches are read as written, so the batch size of options does not apply
+pub fn read_ipc_with(path: &str, options: &ReadOptions) -> Result<Table> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let metadata = read_file_metadata(&mut reader).map_err(ipc_error)?;
+
+    // only the projected columns and the columns the predicate needs are deserialized
+    let projection = options.projection(&metadata.schema.fields)?;
+    // without a predicate the limit is known upfront
+    let limit = match &options.predicate {
+        Some(_) => None,
+        None => options.row_limit,
+    };
+    let reader = FileReader::new(reader, metadata, projection, limit);
+    let fields = reader.schema().fields.clone();
+    let chunks = reader.collect::<arrow2::error::Result<Vec<_>>>().map_err(ipc_error)?;
+
+    let table = options.apply(Table::new(fields, chunks))?;
+    Ok(match options.row_limit {
+        Some(row_limit) => table.head(&row_limit),
+        None => table,
+    })
+}
+
+fn ipc_error(e: arrow2::error::Error) -> Error {
+    match e {
+        arrow2::error::Error::Io(e) => Error::Io(e),
+        e => Error::Ipc(e),
+    }
+}
@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use arrow2::{
+    array::Array,
+    chunk::Chunk,
+    datatypes::Schema,
+    io::ipc::write::{self, FileWriter, WriteOptions},
+};
+
+use crate::core::dataset::Compression;
+use crate::error::{Error, Result};
+
+// IPC buffers can be compressed with LZ4 (frame) or ZSTD (levels don't apply)
+fn codec(compression: &Option<Compression>) -> Result<Option<write::Compression>> {
+    match compression {
+        None | Some(Compression::Uncompressed) => Ok(None),
+        Some(Compression::Lz4Raw) => Ok(Some(write::Compression::LZ4)),
+        Some(Compression::Zstd(_)) => Ok(Some(write::Compression::ZSTD)),
+        Some(c) => Err(Error::InvalidArgument(format!("{:?} is not supported for IPC, use Lz4Raw or Zstd", c))),
+    }
+}
+
+pub fn write_ipc(path: &str, schema: Schema, chunks: &[Chunk<Box<dyn Array>>], compression: &Option<Compression>) -> Result<()> {
+    let options = WriteOptions { compression: codec(compression)? };
+    write_chunks(path, schema, chunks, options).map_err(|e| match e {
+        arrow2::error::Error::Io(e) => Error::Io(e),
+        e => Error::Ipc(e),
+    })
+}
+
+fn write_chunks(path: &str, schema: Schema, chunks: &[Chunk<Box<dyn Array>>], options: WriteOptions) -> arrow2::error::Result<()> {
+    if let Some(path_dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(path_dir)?;
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut writer = FileWriter::try_new(file, &schema, None, options)?;
+    for chunk in chunks {
+        writer.write(chunk, None)?;
+    }
+    writer.finish()
+}
@@ -0,0 +1,46 @@
+use arrow2::datatypes::{DataType, Field};
+
+use crate::error::{Error, Result};
+
+pub mod read;
+pub mod write;
+
+// Options for reading NDJSON files: number of rows the schema is inferred from (all when None) or an explicit schema
+#[derive(Clone, Debug)]
+pub struct JsonOptions {
+    pub infer_rows: Option<usize>,
+    pub schema: Option<Vec<Field>>,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self { infer_rows: Some(1000), schema: None }
+    }
+}
+
+pub(crate) fn json_error(e: arrow2::error::Error) -> Error {
+    match e {
+        arrow2::error::Error::Io(e) => Error::Io(e),
+        e => Error::Json(e),
+    }
+}
+
+// Types that can be both written to and read from JSON: booleans, numbers, strings, and lists & structs of these
+pub(crate) fn check_types(fields: &[Field]) -> Result<()> {
+    fn supported(data_type: &DataType) -> bool {
+        match data_type {
+            DataType::Boolean
+            | DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+            | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+            | DataType::Float32 | DataType::Float64
+            | DataType::Utf8 | DataType::LargeUtf8 => true,
+            DataType::List(field) | DataType::LargeList(field) => supported(&field.data_type),
+            DataType::Struct(fields) => fields.iter().all(|f| supported(&f.data_type)),
+            _ => false,
+        }
+    }
+    match fields.iter().find(|f| !supported(&f.data_type)) {
+        Some(field) => Err(Error::UnsupportedType(format!("{:?} of column {} is not supported for JSON", field.data_type, field.name))),
+        None => Ok(()),
+    }
+}
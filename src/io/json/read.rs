@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use rayon::prelude::*;
+
+use arrow2::{
+    array::{Array, StructArray},
+    chunk::Chunk,
+    datatypes::{DataType, Field},
+    io::ndjson::read,
+};
+
+use crate::core::table::Table;
+use crate::io::ReadOptions;
+use crate::io::json::{JsonOptions, check_types, json_error};
+use crate::error::{Error, Result};
+
+// Default number of rows per chunk
+const BATCH_SIZE: usize = 1024 * 8 * 8;
+
+pub fn read_ndjson(path: &str, options: &JsonOptions) -> Result<Table> {
+    read_ndjson_with(path, options, &ReadOptions::default())
+}
+
+// One column per key of the (object) rows, nested objects & arrays become struct & list columns.
+// Lines are read sequentially, one batch per thread at a time, and the batches are parsed in parallel.
+pub fn read_ndjson_with(path: &str, json: &JsonOptions, options: &ReadOptions) -> Result<Table> {
+    let fields = match &json.schema {
+        Some(schema) => schema.clone(),
+        None => infer_fields(path, json)?,
+    };
+    check_types(&fields)?;
+
+    // only the projected keys and the keys the predicate needs are deserialized
+    let fields = match options.projection(&fields)? {
+        Some(projection) => projection.into_iter().map(|i| fields[i].clone()).collect(),
+        None => fields,
+    };
+    // without a predicate the limit is known upfront
+    let mut remaining = match &options.predicate {
+        Some(_) => usize::MAX,
+        None => options.row_limit.unwrap_or(usize::MAX),
+    };
+    let batch_size = options.batch_size.unwrap_or(BATCH_SIZE);
+
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let mut chunks = Vec::new();
+    let mut done = false;
+    while !done && remaining > 0 {
+        let mut batches = Vec::new();
+        for _ in 0..rayon::current_num_threads() {
+            let n = batch_size.min(remaining);
+            let rows = lines
+                .by_ref()
+                .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                .take(n)
+                .collect::<std::io::Result<Vec<String>>>()?;
+            remaining -= rows.len();
+            if rows.len() < n || remaining == 0 {
+                done = true;
+            }
+            if !rows.is_empty() {
+                batches.push(rows);
+            }
+            if done {
+                break
+            }
+        }
+        let parsed = batches
+            .into_par_iter()
+            .map(|rows| deserialize(&rows, &fields))
+            .collect::<Result<Vec<_>>>()?;
+        chunks.extend(parsed);
+    }
+
+    let table = options.apply(Table::new(fields, chunks))?;
+    Ok(match options.row_limit {
+        Some(row_limit) => table.head(&row_limit),
+        None => table,
+    })
+}
+
+// Fields from the keys of the first infer_rows rows, in order of appearance
+fn infer_fields(path: &str, json: &JsonOptions) -> Result<Vec<Field>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match read::infer(&mut reader, json.infer_rows).map_err(json_error)? {
+        DataType::Struct(fields) => Ok(fields),
+        data_type => Err(Error::UnsupportedType(format!("Rows of {} are {:?}, not objects", path, data_type))),
+    }
+}
+
+fn deserialize(rows: &[String], fields: &[Field]) -> Result<Chunk<Box<dyn Array>>> {
+    let array = read::deserialize(rows, DataType::Struct(fields.to_vec())).map_err(json_error)?;
+    let array = array
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .ok_or_else(|| Error::UnsupportedType(format!("Rows deserialized to {:?}, not a struct", array.data_type())))?;
+    Chunk::try_new(array.values().to_vec()).map_err(json_error)
+}
@@ -0,0 +1,33 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use arrow2::{
+    array::{Array, StructArray},
+    chunk::Chunk,
+    datatypes::{DataType, Field},
+    io::ndjson::write::{FileWriter, Serializer},
+};
+
+use crate::io::json::{check_types, json_error};
+use crate::error::Result;
+
+// One object per row, with a key per column (null values are written as null)
+pub fn write_ndjson(path: &str, fields: &[Field], chunks: &[Chunk<Box<dyn Array>>]) -> Result<()> {
+    check_types(fields)?;
+    write_chunks(path, fields, chunks).map_err(json_error)
+}
+
+fn write_chunks(path: &str, fields: &[Field], chunks: &[Chunk<Box<dyn Array>>]) -> arrow2::error::Result<()> {
+    if let Some(path_dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(path_dir)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    let arrays = chunks
+        .iter()
+        .map(|chunk| StructArray::try_new(DataType::Struct(fields.to_vec()), chunk.arrays().to_vec(), None).map(|a| a.boxed()));
+    for written in FileWriter::new(&mut file, Serializer::new(arrays, vec![])) {
+        written?;
+    }
+    file.flush()?;
+    Ok(())
+}
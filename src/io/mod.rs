@@ -1,2 +1,60 @@
+use arrow2::datatypes::Field;
+
+use crate::core::filter::FilterExpr;
+use crate::core::table::Table;
+use crate::error::{Error, Result};
+
 pub mod parquet;
-pub mod factory;
\ No newline at end of file
+pub mod ipc;
+pub mod csv;
+pub mod json;
+pub mod factory;
+
+// Options for reading files: projection on columns, predicate on rows (parquet skips row groups on their
+// statistics), maximum number of rows and number of rows per chunk
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+    pub columns: Option<Vec<String>>,
+    pub predicate: Option<FilterExpr>,
+    pub row_limit: Option<usize>,
+    pub batch_size: Option<usize>,
+}
+
+impl ReadOptions {
+    // Positions of the fields to read: the projected columns and the columns the predicate needs,
+    // in file order. None when all columns are read.
+    pub fn projection(&self, fields: &[Field]) -> Result<Option<Vec<usize>>> {
+        let columns = match &self.columns {
+            Some(columns) => columns,
+            None => return Ok(None),
+        };
+        let predicate_columns = self.predicate.as_ref().map(|p| p.columns()).unwrap_or_default();
+        if let Some(column) = columns.iter().chain(&predicate_columns).find(|c| !fields.iter().any(|f| &f.name == *c)) {
+            return Err(Error::ColumnNotFound(column.clone()))
+        }
+        let projection = fields
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| columns.contains(&f.name) || predicate_columns.contains(&f.name))
+            .map(|(i, _)| i)
+            .collect();
+        Ok(Some(projection))
+    }
+
+    // Whether reading with these options can leave out rows or columns of a file
+    pub fn is_partial(&self) -> bool {
+        self.columns.is_some() || self.predicate.is_some() || self.row_limit.is_some()
+    }
+
+    // Filter on the predicate and project on the columns (the row limit is left to the reader)
+    pub fn apply(&self, table: Table) -> Result<Table> {
+        let table = match &self.predicate {
+            Some(predicate) => table.filter(predicate)?,
+            None => table,
+        };
+        match &self.columns {
+            Some(columns) => table.select(columns),
+            None => Ok(table),
+        }
+    }
+}
@@ -1,51 +1,210 @@
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use rayon::prelude::*;
 
 use arrow2::{
     array::Array,
     chunk::Chunk,
-    error::Result,
-    io::parquet::read::{self, ArrayIter},
+    datatypes::Field,
+    io::parquet::read::{self, ArrayIter, RowGroupMetaData, statistics::Statistics},
 };
 
+use crate::core::filter::FilterExpr;
 use crate::core::table::Table;
+use crate::io::ReadOptions;
+use crate::error::{Error, Result};
 
-fn deserialize_parallel(iters: &mut [ArrayIter<'static>]) -> Result<Chunk<Box<dyn Array>>> {
+// Next chunk of a row group, None when its columns are exhausted
+fn deserialize_parallel(iters: &mut [ArrayIter<'static>]) -> Result<Option<Chunk<Box<dyn Array>>>> {
     // CPU-bounded
     let arrays = iters
         .par_iter_mut()
         .map(|iter| iter.next().transpose())
-        .collect::<Result<Vec<_>>>()?;
+        .collect::<arrow2::error::Result<Vec<_>>>()
+        .map_err(Error::Parquet)?;
 
-    Chunk::try_new(arrays.into_iter().map(|x| x.unwrap()).collect())
+    if arrays.iter().all(|x| x.is_none()) {
+        return Ok(None)
+    }
+    let arrays = arrays
+        .into_iter()
+        .map(|x| x.ok_or_else(|| Error::Parquet(arrow2::error::Error::OutOfSpec("Column ended before row group".to_string()))))
+        .collect::<Result<Vec<Box<dyn Array>>>>()?;
+    Chunk::try_new(arrays).map(Some).map_err(Error::Parquet)
 }
 
+// Default number of rows per chunk
+const BATCH_SIZE: usize = 1024 * 8 * 8;
+
 pub fn read_parquet(path: &str) -> Result<Table> {
+    read_parquet_with(path, &ReadOptions::default())
+}
+
+// Read only the row groups whose statistics show rows can match predicate, then filter their rows
+pub fn read_parquet_where(path: &str, predicate: &FilterExpr) -> Result<Table> {
+    read_parquet_with(path, &ReadOptions { predicate: Some(predicate.clone()), ..Default::default() })
+}
+
+pub fn read_parquet_with(path: &str, options: &ReadOptions) -> Result<Table> {
+    let (fields, row_groups, limits) = plan(path, options)?;
+    let table = read_row_groups(path, &fields, &row_groups, &limits, options.batch_size.unwrap_or(BATCH_SIZE))?;
+    let table = options.apply(table)?;
+    Ok(match options.row_limit {
+        Some(row_limit) => table.head(&row_limit),
+        None => table,
+    })
+}
+
+// Stream the rows selected by options in tables of at most batch_size rows. Only the column chunks of one
+// row group are held in memory at a time. Datasets consume streams through Dataset::append_stream and
+// Dataset::write_stream; Table::groupby does not, so write the stream partitioned on the keys first.
+pub fn read_parquet_stream(path: &str, options: &ReadOptions) -> Result<ParquetStream> {
+    let (fields, row_groups, limits) = plan(path, options)?;
+    Ok(ParquetStream {
+        path: path.to_string(),
+        fields,
+        row_groups: row_groups.into_iter().zip(limits).collect(),
+        columns: None,
+        options: options.clone(),
+        remaining: options.row_limit,
+    })
+}
+
+pub struct ParquetStream {
+    path: String,
+    fields: Vec<Field>,
+    row_groups: VecDeque<(RowGroupMetaData, Option<usize>)>, // row groups left to read, with their row limit
+    columns: Option<Vec<ArrayIter<'static>>>, // columns of the row group being read
+    options: ReadOptions,
+    remaining: Option<usize>, // rows left under the row limit
+}
+
+impl ParquetStream {
+    fn next_table(&mut self) -> Result<Option<Table>> {
+        loop {
+            if self.remaining == Some(0) {
+                return Ok(None)
+            }
+            let columns = match &mut self.columns {
+                Some(columns) => columns,
+                None => match self.row_groups.pop_front() {
+                    Some((rg, limit)) => {
+                        let mut reader = File::open(&self.path)?;
+                        let batch_size = self.options.batch_size.unwrap_or(BATCH_SIZE);
+                        let columns = read::read_columns_many(&mut reader, &rg, self.fields.clone(), Some(batch_size), limit, None).map_err(Error::Parquet)?;
+                        self.columns.insert(columns)
+                    },
+                    None => return Ok(None),
+                },
+            };
+            match deserialize_parallel(columns)? {
+                Some(chunk) => {
+                    let table = self.options.apply(Table::new(self.fields.clone(), vec![chunk]))?;
+                    if table.num_rows() == 0 {
+                        continue
+                    }
+                    return Ok(Some(match &mut self.remaining {
+                        Some(remaining) => {
+                            let table = table.head(remaining);
+                            *remaining -= table.num_rows();
+                            table
+                        },
+                        None => table,
+                    }))
+                },
+                None => self.columns = None,
+            }
+        }
+    }
+}
+
+impl Iterator for ParquetStream {
+    type Item = Result<Table>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_table();
+        if next.is_err() {
+            // nothing is read after an error
+            self.row_groups.clear();
+            self.columns = None;
+        }
+        next.transpose()
+    }
+}
+
+// Fields to read (projection & predicate columns), row groups that can match the predicate and their row limit
+type Plan = (Vec<Field>, Vec<RowGroupMetaData>, Vec<Option<usize>>);
+
+fn plan(path: &str, options: &ReadOptions) -> Result<Plan> {
     // open the file
     let mut reader = File::open(path)?;
 
     // read Parquet's metadata and infer Arrow schema
-    let metadata = read::read_metadata(&mut reader)?;
-    let schema = read::infer_schema(&metadata)?;
+    let metadata = read::read_metadata(&mut reader).map_err(Error::Parquet)?;
+    let schema = read::infer_schema(&metadata).map_err(Error::Parquet)?;
+
+    // only the projected columns and the columns the predicate needs are read from disk
+    let predicate_columns = options.predicate.as_ref().map(|p| p.columns()).unwrap_or_default();
+    let fields = match options.projection(&schema.fields)? {
+        Some(projection) => projection.into_iter().map(|i| schema.fields[i].clone()).collect(),
+        None => schema.fields.clone(),
+    };
 
-    // we can read the statistics of all parquet's row groups (here for each field)
-    // for field in &schema.fields {
-    //     let statistics = read::statistics::deserialize(field, &metadata.row_groups)?;
-    //     println!("{:#?}, {:#?}", field, statistics);
-    // }
+    let mut row_groups = match &options.predicate {
+        Some(predicate) => {
+            // statistics of the columns in the predicate; columns without (usable) statistics can't prune
+            let statistics = fields
+                .iter()
+                .filter(|field| predicate_columns.contains(&field.name))
+                .filter_map(|field| read::statistics::deserialize(field, &metadata.row_groups).ok().map(|s| (field.name.clone(), s)))
+                .collect::<HashMap<String, Statistics>>();
+            let num_rows = metadata.row_groups.iter().map(|rg| rg.num_rows()).collect::<Vec<usize>>();
+            predicate.may_match(&statistics, &num_rows)?
+                .into_iter()
+                .zip(metadata.row_groups)
+                .filter_map(|(may_match, rg)| may_match.then_some(rg))
+                .collect::<Vec<RowGroupMetaData>>()
+        },
+        None => metadata.row_groups,
+    };
 
-    let chunks = metadata.row_groups
+    // without a predicate the limit is known upfront: only read the row groups (and rows) needed
+    let mut limits = vec![None; row_groups.len()];
+    if let (Some(row_limit), None) = (options.row_limit, &options.predicate) {
+        let mut remaining = row_limit;
+        let mut n = 0;
+        while n < row_groups.len() && remaining > 0 {
+            limits[n] = Some(remaining.min(row_groups[n].num_rows()));
+            remaining -= limits[n].unwrap();
+            n += 1;
+        }
+        row_groups.truncate(n);
+        limits.truncate(n);
+    }
+    Ok((fields, row_groups, limits))
+}
+
+fn read_row_groups(path: &str, fields: &[Field], row_groups: &[RowGroupMetaData], limits: &[Option<usize>], batch_size: usize) -> Result<Table> {
+    let chunks = row_groups
         .par_iter()
-        .map(|rg| {
-            let schema2 = schema.clone();
-            let mut reader2 = File::open(path).unwrap();
-            let mut columns = read::read_columns_many(&mut reader2, rg, schema2.fields, Some(1024 * 8 * 8), None, None).unwrap();        
-            deserialize_parallel(&mut columns).unwrap()
+        .zip(limits)
+        .map(|(rg, limit)| {
+            let mut reader2 = File::open(path)?;
+            let mut columns = read::read_columns_many(&mut reader2, rg, fields.to_vec(), Some(batch_size), *limit, None).map_err(Error::Parquet)?;        
+            // a row group is deserialized in chunks of at most batch_size rows
+            let mut chunks = Vec::new();
+            while let Some(chunk) = deserialize_parallel(&mut columns)? {
+                chunks.push(chunk);
+            }
+            Ok(chunks)
         })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
         .collect::<Vec<_>>();
 
     // create table
-    let table = Table::new( schema.fields, chunks );
+    let table = Table::new( fields.to_vec(), chunks );
 
     Ok(table)
-}
\ No newline at end of file
+}
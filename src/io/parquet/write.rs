@@ -1,22 +1,29 @@
-//! Example demonstrating how to write to parquet in parallel.
-use std::collections::VecDeque;
+//! Writing tables to parquet: row groups are encoded & compressed in parallel, with per column encodings
+//! and row group / page sizes taken from WriteOptions.
+use std::collections::{HashMap, VecDeque};
 
 use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
 
 use arrow2::{
     array::*,
     chunk::Chunk,
     datatypes::*,
     error::{Error, Result},
-    io::parquet::{read::ParquetError, write::*},
+    compute::{aggregate::estimated_bytes_size, cast::{cast, CastOptions}, concatenate::concatenate},
+    io::parquet::{read::ParquetError, write::*, write::WriteOptions as ParquetWriteOptions},
 };
 
-struct Bla {
+use crate::core::dataset::Compression;
+use crate::error;
+
+// The compressed pages of a column chunk, handed to the file writer one at a time
+struct CompressedPages {
     columns: VecDeque<CompressedPage>,
     current: Option<CompressedPage>,
 }
 
-impl Bla {
+impl CompressedPages {
     pub fn new(columns: VecDeque<CompressedPage>) -> Self {
         Self {
             columns,
@@ -25,7 +32,7 @@ impl Bla {
     }
 }
 
-impl FallibleStreamingIterator for Bla {
+impl FallibleStreamingIterator for CompressedPages {
     type Item = CompressedPage;
     type Error = Error;
 
@@ -39,30 +46,199 @@ impl FallibleStreamingIterator for Bla {
     }
 }
 
-pub fn write_parquet(path: &str, schema: Schema, chunks: &Vec<Chunk<Box<dyn Array>>>) -> Result<()> {
-    // declare the options
-    let options = WriteOptions {
-        write_statistics: true,
-        compression: CompressionOptions::Lz4Raw,
-        version: Version::V2,
+// Encoding of the values of a column
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnEncoding {
+    Plain,
+    Dictionary, // RLE dictionary: integers, strings & binary
+    DeltaBinaryPacked, // Integers & temporal types
+    DeltaLengthByteArray, // Strings & binary
+}
+
+// Target size of row groups
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowGroupSize {
+    Rows(usize),
+    Bytes(usize), // Estimated from the (uncompressed) arrow size
+}
+
+// Options for writing parquet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WriteOptions {
+    pub compression: Compression,
+    pub encodings: HashMap<String, ColumnEncoding>, // Per column, plain when not given
+    pub row_group_size: Option<RowGroupSize>, // A row group per chunk when not given
+    pub page_size: Option<usize>, // Target (uncompressed) bytes per data page, a page per row group when not given
+    pub statistics: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::Lz4Raw,
+            encodings: HashMap::new(),
+            row_group_size: None,
+            page_size: None,
+            statistics: true,
+        }
+    }
+}
+
+fn codec(compression: &Compression) -> error::Result<CompressionOptions> {
+    let invalid = |e: ParquetError| error::Error::InvalidArgument(format!("{:?}: {}", compression, e));
+    let codec = match compression {
+        Compression::Uncompressed => CompressionOptions::Uncompressed,
+        Compression::Snappy => CompressionOptions::Snappy,
+        Compression::Gzip(level) => CompressionOptions::Gzip(level.map(GzipLevel::try_new).transpose().map_err(invalid)?),
+        Compression::Brotli(level) => CompressionOptions::Brotli(level.map(BrotliLevel::try_new).transpose().map_err(invalid)?),
+        Compression::Zstd(level) => CompressionOptions::Zstd(level.map(ZstdLevel::try_new).transpose().map_err(invalid)?),
+        Compression::Lz4Raw => CompressionOptions::Lz4Raw,
     };
+    Ok(codec)
+}
+
+fn encoding(column: &ColumnEncoding) -> Encoding {
+    match column {
+        ColumnEncoding::Plain => Encoding::Plain,
+        ColumnEncoding::Dictionary => Encoding::RleDictionary,
+        ColumnEncoding::DeltaBinaryPacked => Encoding::DeltaBinaryPacked,
+        ColumnEncoding::DeltaLengthByteArray => Encoding::DeltaLengthByteArray,
+    }
+}
+
+// Type the values of a dictionary encoded column are packed in
+fn dictionary_type(data_type: &DataType) -> Option<DataType> {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 |
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 |
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary => {
+            Some(DataType::Dictionary(IntegerType::UInt32, Box::new(data_type.clone()), false))
+        },
+        _ => None,
+    }
+}
+
+pub fn write_parquet(path: &str, schema: Schema, chunks: &[Chunk<Box<dyn Array>>], options: &WriteOptions) -> error::Result<()> {
+    // check the encodings upfront, arrow2 would only fail on the first page of a column
+    for (column, column_encoding) in &options.encodings {
+        let field = schema.fields.iter().find(|f| &f.name == column).ok_or_else(|| error::Error::ColumnNotFound(column.clone()))?;
+        let supported = match column_encoding {
+            ColumnEncoding::Dictionary => dictionary_type(&field.data_type).is_some(),
+            e => can_encode(&field.data_type, encoding(e)),
+        };
+        if !supported {
+            return Err(error::Error::InvalidArgument(format!("{:?} can't encode column {} of type {:?}", column_encoding, column, field.data_type)))
+        }
+    }
+    let compression = codec(&options.compression)?;
+
+    write_chunks(path, schema, chunks, options, compression).map_err(|e| match e {
+        Error::Io(e) => error::Error::Io(e),
+        e => error::Error::Parquet(e),
+    })
+}
 
-    let encoding_map = |data_type: &DataType| {
-        match data_type.to_physical_type() {
-            // remaining is plain
-            _ => Encoding::Plain,
+// Chunks of (about) size rows or bytes each
+fn regroup(chunks: &[Chunk<Box<dyn Array>>], size: &RowGroupSize) -> Result<Vec<Chunk<Box<dyn Array>>>> {
+    let num_rows = chunks.iter().map(|c| c.len()).sum::<usize>();
+    let rows = match size {
+        RowGroupSize::Rows(rows) => *rows,
+        RowGroupSize::Bytes(bytes) => {
+            let total = chunks.iter().flat_map(|c| c.arrays()).map(|a| estimated_bytes_size(a.as_ref())).sum::<usize>();
+            (*bytes as f64 / (total as f64 / num_rows.max(1) as f64)) as usize
+        },
+    }.max(1);
+
+    let mut groups = Vec::new();
+    let mut pending: Vec<Chunk<Box<dyn Array>>> = Vec::new();
+    let mut pending_rows = 0;
+    for chunk in chunks {
+        let mut offset = 0;
+        while offset < chunk.len() {
+            let length = (rows - pending_rows).min(chunk.len() - offset);
+            pending.push(Chunk::new(chunk.arrays().iter().map(|a| a.slice(offset, length)).collect()));
+            pending_rows += length;
+            offset += length;
+            if pending_rows == rows {
+                groups.push(concatenate_chunks(&pending)?);
+                pending.clear();
+                pending_rows = 0;
+            }
         }
+    }
+    if !pending.is_empty() {
+        groups.push(concatenate_chunks(&pending)?);
+    }
+    Ok(groups)
+}
+
+fn concatenate_chunks(chunks: &[Chunk<Box<dyn Array>>]) -> Result<Chunk<Box<dyn Array>>> {
+    if chunks.len() == 1 {
+        return Ok(chunks[0].clone())
+    }
+    let columns = (0..chunks[0].arrays().len())
+        .map(|i| concatenate(&chunks.iter().map(|c| c.arrays()[i].as_ref()).collect::<Vec<&dyn Array>>()))
+        .collect::<Result<Vec<Box<dyn Array>>>>()?;
+    Chunk::try_new(columns)
+}
+
+// Encoded pages of a column, split in pages of about page_size bytes. A dictionary encoded column
+// has a single dictionary per column chunk, so it is never split.
+fn column_pages(array: Box<dyn Array>, type_: ParquetType, options: ParquetWriteOptions, encodings: &[Encoding], page_size: Option<usize>) -> Result<Vec<DynIter<'static, Result<EncodedPage>>>> {
+    let rows = match (page_size, array.data_type()) {
+        (Some(page_size), dt) if !matches!(dt, DataType::Dictionary(_, _, _)) && !array.is_empty() => {
+            let bytes_per_row = estimated_bytes_size(array.as_ref()) as f64 / array.len() as f64;
+            ((page_size as f64 / bytes_per_row) as usize).max(1)
+        },
+        _ => return array_to_columns(array, type_, options, encodings),
+    };
+    let mut columns: Vec<Vec<DynIter<'static, Result<EncodedPage>>>> = Vec::new();
+    let mut offset = 0;
+    while offset < array.len() {
+        let length = rows.min(array.len() - offset);
+        for (i, pages) in array_to_columns(array.slice(offset, length), type_.clone(), options, encodings)?.into_iter().enumerate() {
+            if columns.len() <= i {
+                columns.push(Vec::new());
+            }
+            columns[i].push(pages);
+        }
+        offset += length;
+    }
+    Ok(columns.into_iter().map(|pages| DynIter::new(pages.into_iter().flatten())).collect())
+}
+
+fn write_chunks(path: &str, schema: Schema, chunks: &[Chunk<Box<dyn Array>>], write_options: &WriteOptions, compression: CompressionOptions) -> Result<()> {
+    // declare the options
+    let options = ParquetWriteOptions {
+        write_statistics: write_options.statistics,
+        compression,
+        version: Version::V2,
     };
 
-    // declare encodings
-    let encodings = (&schema.fields)
+    // declare encodings; dictionary encoded columns are packed into dictionary arrays first
+    let encodings = schema.fields
         .iter()
-        .map(|f| transverse(&f.data_type, encoding_map))
+        .map(|f| {
+            let column_encoding = write_options.encodings.get(&f.name).map(encoding).unwrap_or(Encoding::Plain);
+            transverse(&f.data_type, |_| column_encoding)
+        })
+        .collect::<Vec<_>>();
+    let dictionaries = schema.fields
+        .iter()
+        .map(|f| match write_options.encodings.get(&f.name) {
+            Some(ColumnEncoding::Dictionary) => dictionary_type(&f.data_type),
+            _ => None,
+        })
         .collect::<Vec<_>>();
 
     // derive the parquet schema (physical types) from arrow's schema.
     let parquet_schema = to_parquet_schema(&schema)?;
 
+    let chunks = match &write_options.row_group_size {
+        Some(size) => regroup(chunks, size)?,
+        None => chunks.to_vec(),
+    };
     let row_groups = chunks.iter().map(|chunk| {
         // write batch to pages; parallelized by rayon
         let columns = chunk
@@ -70,8 +246,19 @@ pub fn write_parquet(path: &str, schema: Schema, chunks: &Vec<Chunk<Box<dyn Arra
             .par_iter()
             .zip(parquet_schema.fields().to_vec())
             .zip(encodings.par_iter())
-            .flat_map(move |((array, type_), encoding)| {
-                let encoded_columns = array_to_columns(array, type_, options, encoding).unwrap();
+            .zip(dictionaries.par_iter())
+            .flat_map(move |(((array, type_), encoding), dictionary)| {
+                let array = match dictionary {
+                    Some(dictionary) => match cast(array.as_ref(), dictionary, CastOptions::default()) {
+                        Ok(array) => array,
+                        Err(e) => return vec![Err(e)],
+                    },
+                    None => array.clone(),
+                };
+                let encoded_columns = match column_pages(array, type_, options, encoding, write_options.page_size) {
+                    Ok(encoded_columns) => encoded_columns,
+                    Err(e) => return vec![Err(e)],
+                };
                 encoded_columns
                     .into_iter()
                     .map(|encoded_pages| {
@@ -93,15 +280,16 @@ pub fn write_parquet(path: &str, schema: Schema, chunks: &Vec<Chunk<Box<dyn Arra
         let row_group = DynIter::new(
             columns
                 .into_iter()
-                .map(|column| Ok(DynStreamingIterator::new(Bla::new(column)))),
+                .map(|column| Ok(DynStreamingIterator::new(CompressedPages::new(column)))),
         );
         Result::Ok(row_group)
     });
 
     // Create a new empty file
-    let path_dir = std::path::Path::new(path).parent().unwrap();
-    std::fs::create_dir_all(path_dir).expect("Create dir failed");
-    let file = std::io::BufWriter::new(std::fs::File::create(path).expect("Create file failed"));
+    if let Some(path_dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(path_dir)?;
+    }
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
 
     let mut writer = FileWriter::try_new(file, schema, options)?;
 
@@ -112,4 +300,4 @@ pub fn write_parquet(path: &str, schema: Schema, chunks: &Vec<Chunk<Box<dyn Arra
     let _size = writer.end(None)?;
 
     Ok(())
-}
\ No newline at end of file
+}